@@ -0,0 +1,77 @@
+//! Routing benchmarks on large grids.
+//!
+//! Run with `cargo bench`. Each router connects opposite corners of a
+//! 4096x4096 maze, either open or with long walls in one orientation, so
+//! the search has to touch most of the grid.
+use maze_routing::{ChangeSet, Maze, RouteOptions, RouterContext};
+use std::time::{Duration, Instant};
+
+const SIZE: usize = 4096;
+const ROUNDS: u32 = 3;
+
+fn open() -> Maze {
+    Maze::new(SIZE, SIZE)
+}
+
+fn vertical_walls() -> Maze {
+    let mut maze = Maze::new(SIZE, SIZE);
+    for i in 1..8 {
+        let x = i * SIZE / 8;
+        if i % 2 == 0 {
+            maze.fill_mut(x, 0, x, SIZE - 2);
+        } else {
+            maze.fill_mut(x, 1, x, SIZE - 1);
+        }
+    }
+    maze
+}
+
+fn horizontal_walls() -> Maze {
+    let mut maze = Maze::new(SIZE, SIZE);
+    for i in 1..8 {
+        let y = i * SIZE / 8;
+        if i % 2 == 0 {
+            maze.fill_mut(0, y, SIZE - 2, y);
+        } else {
+            maze.fill_mut(1, y, SIZE - 1, y);
+        }
+    }
+    maze
+}
+
+fn bench<F: FnMut(&Maze) -> Option<ChangeSet>>(name: &str, mut f: F) {
+    for (kind, maze) in &[
+        ("open", open()),
+        ("vertical", vertical_walls()),
+        ("horizontal", horizontal_walls()),
+    ] {
+        let mut total = Duration::default();
+        for _ in 0..ROUNDS {
+            let start = Instant::now();
+            assert!(f(maze).is_some());
+            total += start.elapsed();
+        }
+        println!("{:<20} {:<12} {:>10.2?}", name, kind, total / ROUNDS);
+    }
+}
+
+fn main() {
+    let (x1, y1, x2, y2) = (0, 0, SIZE - 1, SIZE - 1);
+    bench("lee", |maze| maze.lee(x1, y1, x2, y2));
+    bench("hadlock", |maze| maze.hadlock(x1, y1, x2, y2));
+    bench("mikami_tabuchi", |maze| maze.mikami_tabuchi(x1, y1, x2, y2));
+
+    // the same searches reusing one set of scratch buffers
+    let mut ctx = RouterContext::new(&open());
+    let options = RouteOptions::default();
+    bench("lee_with", |maze| {
+        maze.lee_with(&mut ctx, &options, x1, y1, x2, y2).ok()
+    });
+    bench("hadlock_with", |maze| {
+        maze.hadlock_with(&mut ctx, &options, x1, y1, x2, y2).ok()
+    });
+    bench("mikami_tabuchi_with", |maze| {
+        maze.mikami_tabuchi_with(&mut ctx, &options, x1, y1, x2, y2)
+            .ok()
+    });
+}
@@ -0,0 +1,365 @@
+//! Routing many nets at once
+use super::*;
+use crate::options::Rect;
+use std::thread;
+
+/// A net to be routed
+#[derive(Clone)]
+pub enum Net {
+    /// Two-pin net between (x1, y1) and (x2, y2)
+    TwoPin(usize, usize, usize, usize),
+    /// Multi-pin net
+    MultiPin(Points),
+}
+
+impl Net {
+    fn terminals(&self) -> Vec<(usize, usize)> {
+        match self {
+            Net::TwoPin(x1, y1, x2, y2) => vec![(*x1, *y1), (*x2, *y2)],
+            Net::MultiPin(points) => points.get(),
+        }
+    }
+}
+
+/// Routes of a group of nets that were committed together
+#[derive(Clone, Debug)]
+pub struct TransactionReport {
+    /// combined changes of all routed nets
+    pub changes: ChangeSet,
+    /// index and reason of every net that was not routed
+    pub failed: Vec<(usize, RouteError)>,
+}
+
+/// A group of nets that was not committed because too many nets failed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionError {
+    /// index and reason of every net that was not routed
+    pub failed: Vec<(usize, RouteError)>,
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} nets failed:", self.failed.len())?;
+        for (index, error) in &self.failed {
+            write!(f, " {} ({})", index, error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+/// Result of routing one net speculatively against a snapshot of the maze
+struct Speculation {
+    result: Result<ChangeSet, RouteError>,
+    /// the route only depends on cells inside the first window of the net
+    confined: bool,
+}
+
+impl Maze {
+    /// Route a net with `two_terminal_with` or `multi_terminal_with`,
+    /// returning the error of the last router
+    pub fn route_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        net: &Net,
+    ) -> Result<ChangeSet, RouteError> {
+        let report = match net {
+            Net::TwoPin(x1, y1, x2, y2) => self.two_terminal_with(ctx, options, *x1, *y1, *x2, *y2),
+            Net::MultiPin(points) => self.multi_terminal_with(ctx, options, points),
+        }?;
+        Ok(report.changes)
+    }
+
+    /// Route `nets` one after another and apply the routes, using up to
+    /// `threads` threads.
+    ///
+    /// The result is identical to calling `route_with` and `apply` for each
+    /// net in order. Consecutive nets whose first search windows (see
+    /// `RouteOptions::margin`) do not overlap are routed concurrently
+    /// against the same maze, then committed in order. A net whose route
+    /// left its window, or whose window was written by an earlier net of
+    /// the group, is routed again on the updated maze. Without a margin
+    /// every window is the whole maze and nets are routed one by one. With
+    /// width and spacing rules, windows are grown by the widest band and
+    /// spacing, as a wire keeps nets that far out of its neighbourhood.
+    pub fn route_batch_mut(
+        &mut self,
+        options: &RouteOptions,
+        nets: &[Net],
+        threads: usize,
+    ) -> Vec<Result<ChangeSet, RouteError>> {
+        let threads = max(threads, 1);
+        let reach = self
+            .rules
+            .values()
+            .map(|rule| rule.width() / 2 + rule.spacing())
+            .max()
+            .unwrap_or(0);
+        let windows: Vec<Rect> = nets
+            .iter()
+            .map(|net| {
+                self.window(options.margin, &net.terminals())
+                    .grow(reach, self.m, self.n)
+            })
+            .collect();
+        let mut contexts: Vec<RouterContext> =
+            (0..threads).map(|_| RouterContext::new(self)).collect();
+        let mut results = Vec::with_capacity(nets.len());
+
+        let mut begin = 0;
+        while begin < nets.len() {
+            // group consecutive nets with pairwise disjoint windows
+            let mut end = begin + 1;
+            while end < nets.len()
+                && windows[begin..end]
+                    .iter()
+                    .all(|window| !window.intersects(&windows[end]))
+            {
+                end += 1;
+            }
+
+            let speculations = if end - begin == 1 {
+                vec![None]
+            } else {
+                self.speculate(&mut contexts, options, &nets[begin..end])
+            };
+
+            // commit in order, rerouting nets whose speculation is stale
+            let mut dirty: Vec<Rect> = vec![];
+            for (i, speculation) in (begin..end).zip(speculations) {
+                let result = match speculation {
+                    Some(speculation)
+                        if speculation.confined
+                            && dirty.iter().all(|rect| !rect.intersects(&windows[i])) =>
+                    {
+                        speculation.result
+                    }
+                    _ => {
+                        let result = self.route_with(&mut contexts[0], options, &nets[i]);
+                        if let Ok(changes) = &result {
+                            if let Some(rect) = changes.bounding() {
+                                dirty.push(rect);
+                            }
+                        }
+                        result
+                    }
+                };
+                if let Ok(changes) = &result {
+                    self.apply(changes);
+                }
+                results.push(result);
+            }
+            begin = end;
+        }
+        results
+    }
+
+    /// Route `nets` one after another on a working copy of the maze. The
+    /// routes are returned as one changeset if at least `min_fraction` of
+    /// the nets were routed, e.g. `1.0` requires every net to succeed.
+    pub fn route_transaction(
+        &self,
+        options: &RouteOptions,
+        nets: &[Net],
+        min_fraction: f64,
+    ) -> Result<TransactionReport, TransactionError> {
+        let mut maze = self.clone();
+        let mut ctx = RouterContext::new(self);
+        let mut changes = ChangeSet::default();
+        let mut failed = vec![];
+        for (i, net) in nets.iter().enumerate() {
+            match maze.route_with(&mut ctx, options, net) {
+                Ok(net_changes) => {
+                    maze.apply(&net_changes);
+                    changes = changes.compose(&net_changes);
+                }
+                Err(err) => failed.push((i, err)),
+            }
+        }
+
+        let routed = nets.len() - failed.len();
+        if routed as f64 >= min_fraction * nets.len() as f64 {
+            Ok(TransactionReport { changes, failed })
+        } else {
+            Err(TransactionError { failed })
+        }
+    }
+
+    /// Like `route_transaction`, applying the routes if they are committed
+    /// and leaving the maze untouched otherwise
+    pub fn route_transaction_mut(
+        &mut self,
+        options: &RouteOptions,
+        nets: &[Net],
+        min_fraction: f64,
+    ) -> Result<TransactionReport, TransactionError> {
+        let report = self.route_transaction(options, nets, min_fraction)?;
+        self.apply(&report.changes);
+        Ok(report)
+    }
+
+    /// Route `nets` concurrently against the current maze, each limited to
+    /// its first search window
+    fn speculate(
+        &self,
+        contexts: &mut [RouterContext],
+        options: &RouteOptions,
+        nets: &[Net],
+    ) -> Vec<Option<Speculation>> {
+        let chunk = nets.len().div_ceil(contexts.len());
+        thread::scope(|scope| {
+            let handles: Vec<_> = nets
+                .chunks(chunk)
+                .zip(contexts.iter_mut())
+                .map(|(nets, ctx)| {
+                    scope.spawn(move || {
+                        ctx.confined = true;
+                        let speculations: Vec<Option<Speculation>> = nets
+                            .iter()
+                            .map(|net| {
+                                ctx.escaped = false;
+                                let result = self.route_with(ctx, options, net);
+                                Some(Speculation {
+                                    result,
+                                    confined: !ctx.escaped,
+                                })
+                            })
+                            .collect();
+                        ctx.confined = false;
+                        speculations
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    fn route_sequential(maze: &mut Maze, options: &RouteOptions, nets: &[Net]) -> Vec<bool> {
+        let mut ctx = RouterContext::new(maze);
+        nets.iter()
+            .map(|net| match maze.route_with(&mut ctx, options, net) {
+                Ok(changes) => {
+                    maze.apply(&changes);
+                    true
+                }
+                Err(_) => false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn batch() {
+        let options = RouteOptions {
+            margin: Some(1),
+            ..RouteOptions::default()
+        };
+        let mut nets = vec![];
+        for i in 0..10 {
+            nets.push(Net::TwoPin(i * 10, 0, i * 10 + 5, 5));
+            nets.push(Net::MultiPin(Points::new(&[
+                (i * 10, 10),
+                (i * 10 + 5, 12),
+                (i * 10 + 2, 15),
+            ])));
+        }
+        // spans all the windows above and has to detour around them
+        nets.push(Net::TwoPin(0, 8, 99, 8));
+        nets.push(Net::TwoPin(0, 7, 99, 9));
+
+        let mut maze = Maze::new(100, 20);
+        let results = maze.route_batch_mut(&options, &nets, 4);
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert!(maze.verify());
+        println!("{}", maze);
+
+        let mut sequential = Maze::new(100, 20);
+        route_sequential(&mut sequential, &options, &nets);
+        assert_eq!(maze.to_string(), sequential.to_string());
+    }
+
+    #[test]
+    fn batch_spacing() {
+        // the windows are a row apart, but the spacing reaches across
+        let options = RouteOptions {
+            margin: Some(0),
+            ..RouteOptions::default()
+        };
+        let nets = [Net::TwoPin(0, 0, 4, 0), Net::TwoPin(0, 1, 4, 1)];
+        let mut maze = Maze::new(6, 4);
+        maze.set_rule(0, Rule::new(1, 1));
+        let mut sequential = maze.clone();
+        let results = maze.route_batch_mut(&options, &nets, 2);
+        assert_eq!(
+            route_sequential(&mut sequential, &options, &nets),
+            vec![true, false]
+        );
+        assert_eq!(results[1].as_ref().err(), Some(&RouteError::Unroutable));
+        assert_eq!(maze.to_string(), sequential.to_string());
+        assert!(maze.passes_drc());
+    }
+
+    #[test]
+    fn transaction() {
+        let options = RouteOptions::default();
+        let mut maze = Maze::new(5, 5);
+        maze.fill_mut(2, 0, 2, 3);
+        // the second net takes the only way around the wall
+        let nets = [
+            Net::TwoPin(0, 0, 4, 0),
+            Net::TwoPin(0, 1, 4, 1),
+            Net::MultiPin(Points::new(&[(3, 2), (3, 3)])),
+        ];
+        let before = maze.to_string();
+        let err = maze
+            .route_transaction_mut(&options, &nets, 1.0)
+            .unwrap_err();
+        assert_eq!(err.failed, vec![(1, RouteError::Unroutable)]);
+        assert_eq!(maze.to_string(), before);
+
+        let report = maze.route_transaction_mut(&options, &nets, 0.5).unwrap();
+        assert_eq!(report.failed, vec![(1, RouteError::Unroutable)]);
+        let mut sequential = Maze::new(5, 5);
+        sequential.fill_mut(2, 0, 2, 3);
+        route_sequential(&mut sequential, &options, &nets);
+        assert_eq!(maze.to_string(), sequential.to_string());
+        assert!(maze.verify());
+    }
+
+    quickcheck! {
+        fn qc_batch_same_as_sequential(m: usize, n: usize, margin: Option<usize>, threads: usize, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            let options = RouteOptions {
+                margin: margin.map(|margin| margin % 4),
+                ..RouteOptions::default()
+            };
+            let nets: Vec<Net> = points
+                .into_iter()
+                .map(|(x1, y1, x2, y2)| Net::TwoPin(x1 % m, y1 % n, x2 % m, y2 % n))
+                .collect();
+            let mut maze = Maze::new(m, n);
+            let results = maze.route_batch_mut(&options, &nets, threads % 4 + 1);
+            let mut sequential = Maze::new(m, n);
+            let expected = route_sequential(&mut sequential, &options, &nets);
+            results.iter().map(|result| result.is_ok()).eq(expected)
+                && maze.to_string() == sequential.to_string()
+        }
+    }
+}
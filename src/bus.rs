@@ -0,0 +1,195 @@
+//! Routing buses as bundles of parallel tracks
+use super::*;
+use std::ops::Range;
+
+/// Outcome of `Maze::route_bus_mut`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusReport {
+    /// bits routed side by side as one bundle, in order
+    pub bundles: Vec<Range<usize>>,
+    /// bits routed on their own
+    pub single: Vec<usize>,
+    /// bit and reason of every bit that was not routed
+    pub unrouted: Vec<(usize, RouteError)>,
+}
+
+impl Maze {
+    /// Route a bus from `sources` to `sinks`, bit i from the i-th source to
+    /// the i-th sink, and apply the routes. Sources and sinks must be lines
+    /// of adjacent cells. The bits run side by side in their order like a
+    /// differential pair, where the bus does not fit it is split in halves
+    /// that are routed in turn, down to single bits routed by
+    /// `two_terminal_with`. No route runs through the pins of other bits.
+    pub fn route_bus_mut(
+        &mut self,
+        options: &RouteOptions,
+        sources: &[(usize, usize)],
+        sinks: &[(usize, usize)],
+    ) -> BusReport {
+        assert_eq!(sources.len(), sinks.len(), "bus widths differ");
+        let mut ctx = RouterContext::new(self);
+        let mut report = BusReport {
+            bundles: vec![],
+            single: vec![],
+            unrouted: vec![],
+        };
+        // lower bits first
+        let mut pending = vec![];
+        pending.push(0..sources.len());
+        while let Some(bits) = pending.pop() {
+            let restore = self.apply(&self.block_pins(sources, sinks, &bits));
+            if bits.len() == 1 {
+                let ((x1, y1), (x2, y2)) = (sources[bits.start], sinks[bits.start]);
+                let result = self.two_terminal_with(&mut ctx, options, x1, y1, x2, y2);
+                self.apply(&restore);
+                match result {
+                    Ok(chain) => {
+                        self.apply(&chain.changes);
+                        report.single.push(bits.start);
+                    }
+                    Err(err) => report.unrouted.push((bits.start, err.into())),
+                }
+                continue;
+            }
+            let result = self.bundle_with(
+                &mut ctx,
+                options,
+                &sources[bits.clone()],
+                &sinks[bits.clone()],
+            );
+            self.apply(&restore);
+            match result {
+                Ok(bundle) => {
+                    self.apply(&self.bundle_changes(&bundle, options.net));
+                    report.bundles.push(bits);
+                }
+                Err(_) => {
+                    let middle = bits.start + bits.len() / 2;
+                    pending.push(middle..bits.end);
+                    pending.push(bits.start..middle);
+                }
+            }
+        }
+        report
+    }
+
+    /// Changes blocking the empty pins of the bits not in `bits`
+    fn block_pins(
+        &self,
+        sources: &[(usize, usize)],
+        sinks: &[(usize, usize)],
+        bits: &Range<usize>,
+    ) -> ChangeSet {
+        let own = |cell: &(usize, usize)| {
+            sources[bits.clone()].contains(cell) || sinks[bits.clone()].contains(cell)
+        };
+        let mut changes = ChangeSet::default();
+        for cell in sources.iter().chain(sinks) {
+            if !own(cell) && self.map[*cell] == CellState::Empty {
+                changes.changes.push((cell.0, cell.1, CellState::Blocked));
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn bus() {
+        let mut maze = Maze::new(12, 10);
+        let sources: Vec<(usize, usize)> = (2..10).map(|y| (0, y)).collect();
+        let sinks: Vec<(usize, usize)> = (2..10).map(|x| (x, 0)).collect();
+        let report = maze.route_bus_mut(&RouteOptions::default(), &sources, &sinks);
+        println!("{}", maze);
+        assert!(maze.verify());
+        assert_eq!(report.bundles, vec![0..8]);
+        assert_eq!(
+            maze.to_string(),
+            "╺━━━━━━━━┓..\n╺━━━━━━━┓┃..\n╺━━━━━━┓┃┃..\n╺━━━━━┓┃┃┃..\n╺━━━━┓┃┃┃┃..\n\
+             ╺━━━┓┃┃┃┃┃..\n╺━━┓┃┃┃┃┃┃..\n╺━┓┃┃┃┃┃┃┃..\n..┃┃┃┃┃┃┃┃..\n..╹╹╹╹╹╹╹╹..\n"
+        );
+
+        // the upper half of the bus does not fit through the gap
+        let mut maze = Maze::new(12, 10).fill(6, 4, 6, 9);
+        let sources: Vec<(usize, usize)> = (0..8).map(|y| (0, y)).collect();
+        let sinks: Vec<(usize, usize)> = (0..8).map(|y| (11, y)).collect();
+        let report = maze.route_bus_mut(&RouteOptions::default(), &sources, &sinks);
+        println!("{}", maze);
+        assert!(maze.verify());
+        assert_eq!(report.bundles, vec![0..4]);
+        let unrouted: Vec<usize> = report.unrouted.iter().map(|(bit, _)| *bit).collect();
+        assert_eq!(unrouted, vec![4, 5, 6, 7]);
+
+        // each bit takes its own gap
+        let mut maze = Maze::new(12, 10)
+            .fill(6, 0, 6, 0)
+            .fill(6, 2, 6, 3)
+            .fill(6, 5, 6, 9);
+        let report = maze.route_bus_mut(
+            &RouteOptions::default(),
+            &[(0, 3), (0, 2)],
+            &[(11, 3), (11, 2)],
+        );
+        println!("{}", maze);
+        assert!(maze.verify());
+        assert!(report.bundles.is_empty());
+        assert_eq!(report.single, vec![0, 1]);
+
+        // the first bit may not run through the pins of the others
+        let mut maze = Maze::new(6, 5);
+        let options = RouteOptions {
+            net: 2,
+            ..RouteOptions::default()
+        };
+        let sources = [(0, 0), (1, 0), (2, 2)];
+        let sinks = [(5, 3), (5, 2), (4, 0)];
+        let report = maze.route_bus_mut(&options, &sources, &sinks);
+        println!("{}", maze);
+        assert!(maze.verify());
+        assert_eq!(report.single, vec![0, 1, 2]);
+        for (x, y) in sources.iter().chain(&sinks) {
+            assert_eq!(maze.terminal(*x, *y).unwrap().pin, 2);
+        }
+    }
+
+    quickcheck! {
+        fn qc_bus(m: usize, n: usize, blocks: Vec<(usize, usize)>, points: (usize, usize, usize, usize), width: usize, sides: (bool, bool)) -> bool {
+            if m < 2 || n < 2 {
+                return true;
+            }
+            // check oom
+            if m > 30 || n > 30 {
+                return true
+            }
+
+            let mut maze = Maze::new(m, n);
+            for (x, y) in blocks {
+                maze.fill_mut(x % m, y % n, x % m, y % n);
+            }
+            let width = width % min(m, n) + 1;
+            // a line of pins starting at (x, y)
+            let pins = |x: usize, y: usize, vertical: bool| -> Vec<(usize, usize)> {
+                if vertical {
+                    let y = y % (n - width + 1);
+                    (0..width).map(|i| (x % m, y + i)).collect()
+                } else {
+                    let x = x % (m - width + 1);
+                    (0..width).map(|i| (x + i, y % n)).collect()
+                }
+            };
+            let sources = pins(points.0, points.1, sides.0);
+            let sinks = pins(points.2, points.3, sides.1);
+            let report = maze.route_bus_mut(&RouteOptions::default(), &sources, &sinks);
+            // every bit is reported once
+            let mut bits: Vec<usize> = report.bundles.iter().cloned().flatten().collect();
+            bits.extend(&report.single);
+            bits.extend(report.unrouted.iter().map(|(bit, _)| *bit));
+            bits.sort_unstable();
+            maze.verify() && bits == (0..width).collect::<Vec<_>>()
+        }
+    }
+}
@@ -0,0 +1,519 @@
+//! Chains of routers tried one after another
+use super::*;
+use std::thread;
+
+/// An algorithm routing one net
+pub trait Router: Send + Sync {
+    /// Name of the algorithm in reports
+    fn name(&self) -> String;
+
+    /// Route `net` on `maze`, reusing the scratch buffers in `ctx` within
+    /// the limits of `options`
+    fn route(
+        &self,
+        maze: &Maze,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        net: &Net,
+    ) -> Result<ChangeSet, RouteError>;
+}
+
+impl Router for Algorithm {
+    fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    /// Multi-pin nets are routed with `Maze::lee_multi_with` by Lee, the
+    /// other Lee variants and Hadlock return `RouteError::Unsupported`
+    fn route(
+        &self,
+        maze: &Maze,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        net: &Net,
+    ) -> Result<ChangeSet, RouteError> {
+        use Algorithm::*;
+        match (net, self) {
+            (Net::TwoPin(x1, y1, x2, y2), _) => {
+                let (x1, y1, x2, y2) = (*x1, *y1, *x2, *y2);
+                match self {
+                    Lee => maze.lee_with(ctx, options, x1, y1, x2, y2),
+                    LeeMinimumCrossing => {
+                        maze.lee_minimum_crossing_with(ctx, options, x1, y1, x2, y2)
+                    }
+                    LeeMinimumEdgeEffect => {
+                        maze.lee_minimum_edge_effect_with(ctx, options, x1, y1, x2, y2)
+                    }
+                    Hadlock => maze.hadlock_with(ctx, options, x1, y1, x2, y2),
+                    MikamiTabuchi => maze.mikami_tabuchi_with(ctx, options, x1, y1, x2, y2),
+                    SingleTrunk => maze.stst_with(options, &Points::new(&[(x1, y1), (x2, y2)])),
+                }
+            }
+            (Net::MultiPin(points), SingleTrunk) => maze.stst_with(options, points),
+            (Net::MultiPin(points), MikamiTabuchi) => {
+                maze.mikami_tabuchi_multi_with(ctx, options, points)
+            }
+            (Net::MultiPin(points), Lee) => maze.lee_multi_with(ctx, options, points),
+            (Net::MultiPin(_), LeeMinimumCrossing | LeeMinimumEdgeEffect | Hadlock) => {
+                Err(RouteError::Unsupported)
+            }
+        }
+    }
+}
+
+/// What makes a route good
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Objective {
+    /// Fewest cells
+    Shortest,
+    /// Fewest turns
+    FewestBends,
+    /// Fewest wires crossed
+    FewestCrossings,
+    /// Fewest obstacles and bent wires next to the route
+    FewestEdges,
+}
+
+impl Objective {
+    /// Cost of `changes` computed against `maze`, the lowest is best
+    pub fn score(&self, maze: &Maze, changes: &ChangeSet) -> usize {
+        use CellState::*;
+        let states = changes.final_states();
+        match self {
+            Objective::Shortest => states.len(),
+            Objective::FewestBends => states
+                .values()
+                .filter(|state| !matches!(state, Empty | Blocked | LR | UD | Cross | Terminal))
+                .count(),
+            Objective::FewestCrossings => changes.crossings(),
+            Objective::FewestEdges => states
+                .keys()
+                .map(|(x, y)| {
+                    [Direction::L, Direction::R, Direction::U, Direction::D]
+                        .iter()
+                        .filter_map(|direction| maze.map.neighbor(*x, *y, *direction))
+                        .filter(|neighbor| !matches!(maze.map[*neighbor], Empty | LR | UD))
+                        .count()
+                })
+                .sum(),
+        }
+    }
+}
+
+/// Route found by `RouterChain::route`
+#[derive(Clone, Debug)]
+pub struct ChainReport {
+    pub changes: ChangeSet,
+    /// name of the router that found the route
+    pub router: String,
+    /// name and reason of every router that failed before, or for
+    /// `RouterChain::best` at all, in order
+    pub failed: Vec<(String, RouteError)>,
+}
+
+/// Every router of a `RouterChain` failed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainError {
+    /// name and reason of every router, in order
+    pub failed: Vec<(String, RouteError)>,
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "all routers failed:")?;
+        for (router, error) in &self.failed {
+            write!(f, " {} ({})", router, error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+impl From<ChainError> for RouteError {
+    /// The reason of the last router
+    fn from(err: ChainError) -> RouteError {
+        match err.failed.last() {
+            Some((_, err)) => *err,
+            None => RouteError::Unroutable,
+        }
+    }
+}
+
+/// Routers tried in order until one routes the net
+#[derive(Default)]
+pub struct RouterChain {
+    routers: Vec<Box<dyn Router>>,
+    /// most wires a route may cross, `None` for any
+    max_crossings: Option<usize>,
+}
+
+impl RouterChain {
+    /// Chain without routers
+    pub fn new() -> RouterChain {
+        RouterChain::default()
+    }
+
+    /// Chain of `algorithms` in order
+    pub fn from_algorithms(algorithms: &[Algorithm]) -> RouterChain {
+        let mut chain = RouterChain::new();
+        for algorithm in algorithms {
+            chain.push(*algorithm);
+        }
+        chain
+    }
+
+    /// The routers of `Maze::two_terminal_with`
+    pub fn two_pin() -> RouterChain {
+        RouterChain::from_algorithms(&class::TWO_PIN)
+    }
+
+    /// The routers of `Maze::multi_terminal_with`
+    pub fn multi_pin() -> RouterChain {
+        RouterChain::from_algorithms(&class::MULTI_PIN)
+    }
+
+    /// Routers most likely to reach `objective` first, falling back to the
+    /// others
+    pub fn for_objective(objective: Objective) -> RouterChain {
+        use Algorithm::*;
+        RouterChain::from_algorithms(match objective {
+            Objective::Shortest => &[Lee, Hadlock, MikamiTabuchi],
+            Objective::FewestBends => &[MikamiTabuchi, SingleTrunk, Lee],
+            Objective::FewestCrossings => &[LeeMinimumCrossing, Lee],
+            Objective::FewestEdges => &[LeeMinimumEdgeEffect, Lee],
+        })
+    }
+
+    /// Append a router
+    pub fn push<R: Router + 'static>(&mut self, router: R) {
+        self.routers.push(Box::new(router));
+    }
+
+    /// Reject routes crossing more than `max_crossings` wires as
+    /// `RouteError::Unroutable`, `None` for any
+    pub fn set_max_crossings(&mut self, max_crossings: Option<usize>) {
+        self.max_crossings = max_crossings;
+    }
+
+    /// Route `net` with the router at `index`, within the crossings allowed
+    fn route_one(
+        &self,
+        index: usize,
+        maze: &Maze,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        net: &Net,
+    ) -> Result<ChangeSet, RouteError> {
+        let changes = self.routers[index].route(maze, ctx, options, net)?;
+        match self.max_crossings {
+            Some(max_crossings) if changes.crossings() > max_crossings => {
+                Err(RouteError::Unroutable)
+            }
+            _ => Ok(changes),
+        }
+    }
+
+    /// Names of the routers in order
+    pub fn names(&self) -> Vec<String> {
+        self.routers.iter().map(|router| router.name()).collect()
+    }
+
+    /// Try the routers in order. Each gets the full budget of `options`.
+    pub fn route(
+        &self,
+        maze: &Maze,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        net: &Net,
+    ) -> Result<ChainReport, ChainError> {
+        let mut failed = vec![];
+        for (i, router) in self.routers.iter().enumerate() {
+            match self.route_one(i, maze, ctx, options, net) {
+                Ok(changes) => {
+                    return Ok(ChainReport {
+                        changes,
+                        router: router.name(),
+                        failed,
+                    })
+                }
+                Err(err) => failed.push((router.name(), err)),
+            }
+        }
+        Err(ChainError { failed })
+    }
+
+    /// Run every router on `net` against the same maze and keep the route
+    /// with the lowest `objective` score, then the fewest cells, then the
+    /// earliest router. Up to `threads` routers run concurrently.
+    pub fn best(
+        &self,
+        maze: &Maze,
+        options: &RouteOptions,
+        net: &Net,
+        objective: Objective,
+        threads: usize,
+    ) -> Result<ChainReport, ChainError> {
+        let threads = max(threads, 1);
+        let results: Vec<Result<ChangeSet, RouteError>> = if threads == 1 {
+            let mut ctx = RouterContext::new(maze);
+            (0..self.routers.len())
+                .map(|i| self.route_one(i, maze, &mut ctx, options, net))
+                .collect()
+        } else {
+            let chunk = max(self.routers.len().div_ceil(threads), 1);
+            thread::scope(|scope| {
+                let handles: Vec<_> = (0..self.routers.len())
+                    .step_by(chunk)
+                    .map(|start| {
+                        let end = min(start + chunk, self.routers.len());
+                        scope.spawn(move || {
+                            let mut ctx = RouterContext::new(maze);
+                            (start..end)
+                                .map(|i| self.route_one(i, maze, &mut ctx, options, net))
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .flat_map(|handle| handle.join().unwrap())
+                    .collect()
+            })
+        };
+
+        let mut best: Option<(usize, usize, usize, ChangeSet)> = None;
+        let mut failed = vec![];
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(changes) => {
+                    let score = objective.score(maze, &changes);
+                    let cells = changes.final_states().len();
+                    if best.as_ref().is_none_or(|(old_score, old_cells, _, _)| {
+                        (score, cells) < (*old_score, *old_cells)
+                    }) {
+                        best = Some((score, cells, i, changes));
+                    }
+                }
+                Err(err) => failed.push((self.routers[i].name(), err)),
+            }
+        }
+        match best {
+            Some((_, _, i, changes)) => Ok(ChainReport {
+                changes,
+                router: self.routers[i].name(),
+                failed,
+            }),
+            None => Err(ChainError { failed }),
+        }
+    }
+}
+
+impl Maze {
+    /// Apply the best route of `chain` for `net`, see `RouterChain::best`
+    pub fn route_best_mut(
+        &mut self,
+        chain: &RouterChain,
+        options: &RouteOptions,
+        net: &Net,
+        objective: Objective,
+        threads: usize,
+    ) -> Result<ChainReport, ChainError> {
+        let report = chain.best(self, options, net, objective, threads)?;
+        self.apply(&report.changes);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    /// Gives up on every net
+    struct Never;
+
+    impl Router for Never {
+        fn name(&self) -> String {
+            String::from("never")
+        }
+
+        fn route(
+            &self,
+            _maze: &Maze,
+            _ctx: &mut RouterContext,
+            _options: &RouteOptions,
+            _net: &Net,
+        ) -> Result<ChangeSet, RouteError> {
+            Err(RouteError::BudgetExceeded)
+        }
+    }
+
+    #[test]
+    fn router_chain() {
+        // the trunk through (2, 1) is blocked
+        let maze = Maze::new(5, 3).fill(2, 1, 2, 1);
+        let mut ctx = RouterContext::new(&maze);
+        let options = RouteOptions::default();
+        let mut chain = RouterChain::new();
+        chain.push(Never);
+        chain.push(Algorithm::SingleTrunk);
+        chain.push(Algorithm::Lee);
+        assert_eq!(chain.names(), vec!["never", "SingleTrunk", "Lee"]);
+
+        let report = chain
+            .route(&maze, &mut ctx, &options, &Net::TwoPin(0, 1, 4, 1))
+            .unwrap();
+        assert_eq!(report.router, "Lee");
+        assert_eq!(
+            report.failed,
+            vec![
+                (String::from("never"), RouteError::BudgetExceeded),
+                (String::from("SingleTrunk"), RouteError::Unroutable)
+            ]
+        );
+        assert_eq!(Some(report.changes), maze.lee(0, 1, 4, 1));
+
+        let net = Net::TwoPin(0, 0, 2, 1);
+        let err = chain.route(&maze, &mut ctx, &options, &net).unwrap_err();
+        assert_eq!(err.failed.len(), 3);
+        assert_eq!(
+            err.to_string(),
+            "all routers failed: never (search budget exceeded) SingleTrunk (unroutable) Lee (unroutable)"
+        );
+
+        // the default chains route like two_terminal and multi_terminal
+        let report = RouterChain::two_pin()
+            .route(&maze, &mut ctx, &options, &Net::TwoPin(0, 0, 4, 2))
+            .unwrap();
+        assert_eq!(report.router, "MikamiTabuchi");
+        assert_eq!(Some(report.changes), maze.two_terminal(0, 0, 4, 2));
+        let points = Points::new(&[(0, 0), (4, 2), (0, 2)]);
+        let report = RouterChain::multi_pin()
+            .route(&maze, &mut ctx, &options, &Net::MultiPin(points.clone()))
+            .unwrap();
+        assert_eq!(Some(report.changes), maze.multi_terminal(&points));
+        // which report the router that found the route
+        let report = maze
+            .two_terminal_with(&mut ctx, &options, 0, 1, 4, 1)
+            .unwrap();
+        assert_eq!(report.router, "MikamiTabuchi");
+        let report = maze
+            .multi_terminal_with(&mut ctx, &options, &Points::new(&[(0, 1), (4, 1)]))
+            .unwrap();
+        assert_eq!(report.router, "MikamiTabuchi");
+        assert_eq!(
+            report.failed,
+            vec![(String::from("SingleTrunk"), RouteError::Unroutable)]
+        );
+
+        // routes crossing too many wires are rejected
+        let mut crossed = Maze::new(5, 3);
+        assert!(crossed.lee_mut(2, 0, 2, 2));
+        let mut chain = RouterChain::from_algorithms(&[Algorithm::SingleTrunk, Algorithm::Lee]);
+        chain.set_max_crossings(Some(0));
+        let net = Net::TwoPin(0, 1, 4, 1);
+        let err = chain.route(&crossed, &mut ctx, &options, &net).unwrap_err();
+        assert_eq!(
+            err.failed,
+            vec![
+                (String::from("SingleTrunk"), RouteError::Unroutable),
+                (String::from("Lee"), RouteError::Unroutable)
+            ]
+        );
+        assert_eq!(RouteError::from(err), RouteError::Unroutable);
+        chain.set_max_crossings(Some(1));
+        let report = chain.route(&crossed, &mut ctx, &options, &net).unwrap();
+        assert_eq!(report.router, "SingleTrunk");
+
+        let chain = RouterChain::for_objective(Objective::FewestCrossings);
+        assert_eq!(chain.names()[0], "LeeMinimumCrossing");
+        // which does not route multi-pin nets
+        let report = chain
+            .route(&maze, &mut ctx, &options, &Net::MultiPin(points))
+            .unwrap();
+        assert_eq!(report.router, "Lee");
+        assert_eq!(
+            report.failed,
+            vec![(String::from("LeeMinimumCrossing"), RouteError::Unsupported)]
+        );
+    }
+
+    #[test]
+    fn best_of_chain() {
+        use Algorithm::*;
+        let mut maze = Maze::new(6, 6);
+        maze.two_terminal_mut(3, 0, 3, 4);
+        let chain = RouterChain::from_algorithms(&[
+            SingleTrunk,
+            MikamiTabuchi,
+            Lee,
+            LeeMinimumCrossing,
+            Hadlock,
+        ]);
+        let options = RouteOptions::default();
+        let net = Net::TwoPin(0, 2, 5, 2);
+        for objective in &[
+            Objective::Shortest,
+            Objective::FewestBends,
+            Objective::FewestCrossings,
+            Objective::FewestEdges,
+        ] {
+            let report = chain.best(&maze, &options, &net, *objective, 1).unwrap();
+            let parallel = chain.best(&maze, &options, &net, *objective, 3).unwrap();
+            assert_eq!(report.router, parallel.router);
+            assert_eq!(report.changes, parallel.changes);
+            // no router does better
+            let mut ctx = RouterContext::new(&maze);
+            for router in &chain.routers {
+                if let Ok(changes) = router.route(&maze, &mut ctx, &options, &net) {
+                    assert!(
+                        objective.score(&maze, &changes) >= objective.score(&maze, &report.changes)
+                    );
+                }
+            }
+        }
+        // the straight trunk crosses the wire
+        let report = chain
+            .best(&maze, &options, &net, Objective::FewestBends, 2)
+            .unwrap();
+        assert_eq!(report.router, "SingleTrunk");
+        let report = chain
+            .best(&maze, &options, &net, Objective::FewestCrossings, 2)
+            .unwrap();
+        assert_eq!(report.router, "LeeMinimumCrossing");
+        assert_eq!(report.changes.crossings(), 0);
+
+        assert!(maze
+            .route_best_mut(&chain, &options, &net, Objective::Shortest, 4)
+            .is_ok());
+        assert!(maze.verify());
+    }
+
+    quickcheck! {
+        fn qc_best_shortest(m: usize, n: usize, blocks: Vec<(usize, usize)>, points: (usize, usize, usize, usize), threads: usize) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            let mut maze = Maze::new(m, n);
+            for (x, y) in blocks {
+                maze.fill_mut(x % m, y % n, x % m, y % n);
+            }
+            let (x1, y1, x2, y2) = points;
+            let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+            let chain = RouterChain::for_objective(Objective::Shortest);
+            let net = Net::TwoPin(x1, y1, x2, y2);
+            let report = chain.best(&maze, &RouteOptions::default(), &net, Objective::Shortest, threads % 4);
+            match (report, maze.lee(x1, y1, x2, y2)) {
+                (Ok(report), Some(lee)) => report.changes.final_states().len() == lee.final_states().len(),
+                (Err(err), None) => err.failed.len() == 3,
+                _ => false,
+            }
+        }
+    }
+}
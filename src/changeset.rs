@@ -0,0 +1,323 @@
+//! Combining and comparing changesets
+use super::*;
+use std::collections::BTreeMap;
+
+impl ChangeSet {
+    /// Final state of every changed cell, the last change wins
+    pub(crate) fn final_states(&self) -> BTreeMap<(usize, usize), CellState> {
+        self.changes
+            .iter()
+            .map(|(x, y, state)| ((*x, *y), *state))
+            .collect()
+    }
+
+    /// Final state and terminal of every cell this changeset changes
+    /// compared to `maze`
+    fn final_cells(&self, maze: &Maze) -> BTreeMap<(usize, usize), (CellState, Option<Terminal>)> {
+        let mut cells = BTreeMap::new();
+        for ((x, y), state) in self.final_states() {
+            let terminal = match state {
+                CellState::Terminal => Some(maze.terminal(x, y).unwrap_or_default()),
+                _ => None,
+            };
+            cells.insert((x, y), (state, terminal));
+        }
+        for (x, y, terminal) in &self.terminals {
+            let state = cells
+                .get(&(*x, *y))
+                .map_or(maze.map[(*x, *y)], |(state, _)| *state);
+            if state == CellState::Terminal {
+                cells.insert((*x, *y), (state, Some(*terminal)));
+            }
+        }
+        cells.retain(|(x, y), (state, terminal)| {
+            *state != maze.map[(*x, *y)] || *terminal != maze.terminal(*x, *y)
+        });
+        cells
+    }
+
+    fn from_cells(cells: BTreeMap<(usize, usize), (CellState, Option<Terminal>)>) -> ChangeSet {
+        let mut changes = ChangeSet::default();
+        for ((x, y), (state, terminal)) in cells {
+            changes.changes.push((x, y, state));
+            if let Some(terminal) = terminal {
+                changes.terminals.push((x, y, terminal));
+            }
+        }
+        changes
+    }
+}
+
+#[wasm_bindgen]
+impl ChangeSet {
+    /// Number of changes
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.terminals.is_empty()
+    }
+
+    /// One change per cell with the same effect, sorted by position
+    pub fn normalize(&self) -> ChangeSet {
+        self.compose(&ChangeSet::default())
+    }
+
+    /// Changeset with the effect of applying `self`, then `other`
+    pub fn compose(&self, other: &ChangeSet) -> ChangeSet {
+        let mut states = self.final_states();
+        let mut terminals: BTreeMap<(usize, usize), Terminal> = self
+            .terminals
+            .iter()
+            .map(|(x, y, terminal)| ((*x, *y), *terminal))
+            .collect();
+        for ((x, y), state) in other.final_states() {
+            if state != CellState::Terminal {
+                terminals.remove(&(x, y));
+            }
+            states.insert((x, y), state);
+        }
+        for (x, y, terminal) in &other.terminals {
+            terminals.insert((*x, *y), *terminal);
+        }
+        ChangeSet {
+            changes: states
+                .into_iter()
+                .map(|((x, y), state)| (x, y, state))
+                .collect(),
+            terminals: terminals
+                .into_iter()
+                .map(|((x, y), terminal)| (x, y, terminal))
+                .collect(),
+        }
+    }
+
+    /// Combine two changesets computed against `maze` into one. Returns
+    /// `None` if they conflict: both change the same cell to a different
+    /// state, and the cell is neither a terminal of the same pin connected
+    /// on different sides, nor a wire of one crossing a straight wire of the
+    /// other at a right angle.
+    pub fn merge(&self, other: &ChangeSet, maze: &Maze) -> Option<ChangeSet> {
+        let mut cells = self.final_cells(maze);
+        for ((x, y), (state, terminal)) in other.final_cells(maze) {
+            let merged = match cells.get(&(x, y)) {
+                Some((old, old_terminal)) => match (old_terminal, terminal) {
+                    (Some(a), Some(b)) => {
+                        let terminal = merge_terminal(maze.terminal(x, y), *a, b)?;
+                        (CellState::Terminal, Some(terminal))
+                    }
+                    (None, None) => (merge_cell(maze.map[(x, y)], *old, state)?, None),
+                    _ => return None,
+                },
+                None => (state, terminal),
+            };
+            cells.insert((x, y), merged);
+        }
+        Some(ChangeSet::from_cells(cells))
+    }
+
+    /// Whether two changesets computed against `maze` cannot be merged
+    pub fn conflicts_with(&self, other: &ChangeSet, maze: &Maze) -> bool {
+        self.merge(other, maze).is_none()
+    }
+}
+
+/// Merge two changes of one terminal from `base` to `a` and to `b`
+fn merge_terminal(base: Option<Terminal>, a: Terminal, b: Terminal) -> Option<Terminal> {
+    if a.pin != b.pin {
+        return None;
+    }
+    let base = base.map_or([false; 4], |base| base.directions());
+    let mut merged = a;
+    for (i, direction) in [Direction::L, Direction::R, Direction::U, Direction::D]
+        .iter()
+        .enumerate()
+    {
+        if a.directions()[i] && b.directions()[i] && !base[i] {
+            // both use the same side of the terminal
+            return None;
+        }
+        if b.directions()[i] {
+            merged.connect(*direction);
+        }
+    }
+    Some(merged)
+}
+
+/// Merge two changes of one cell from `base` to `a` and to `b`
+fn merge_cell(base: CellState, a: CellState, b: CellState) -> Option<CellState> {
+    use CellState::*;
+    if a == b && (a == Blocked || a == Empty) {
+        // blocked by both, or cleaned by both
+        return Some(a);
+    }
+    if a == Blocked || b == Blocked || base == Blocked || base == Terminal {
+        return None;
+    }
+
+    // L, R, U, D
+    let (base, a, b) = (base.extract(), a.extract(), b.extract());
+    let straight = |directions: &[bool; 4]| {
+        *directions == [true, true, false, false] || *directions == [false, false, true, true]
+    };
+    let mut merged = base;
+    let mut added_a = [false; 4];
+    let mut added_b = [false; 4];
+    for i in 0..4 {
+        if base[i] && !(a[i] && b[i]) {
+            // removed by one of them
+            return None;
+        }
+        if a[i] && b[i] && !base[i] {
+            // both use the same side of the cell
+            return None;
+        }
+        added_a[i] = a[i] && !base[i];
+        added_b[i] = b[i] && !base[i];
+        merged[i] |= a[i] || b[i];
+    }
+
+    // only wires crossing at a right angle stay apart, anything else
+    // connects them
+    let parts = [base, added_a, added_b];
+    if parts
+        .iter()
+        .filter(|part| part.iter().any(|d| *d))
+        .all(straight)
+    {
+        CellState::from_directions(merged)
+    } else {
+        None
+    }
+}
+
+#[wasm_bindgen]
+impl Maze {
+    /// Changeset turning this maze into `other` of the same size
+    pub fn diff(&self, other: &Maze) -> ChangeSet {
+        assert_eq!((self.m, self.n), (other.m, other.n));
+        let mut changes = ChangeSet::default();
+        for y in 0..self.n {
+            for x in 0..self.m {
+                if self.map[(x, y)] != other.map[(x, y)] {
+                    changes.changes.push((x, y, other.map[(x, y)]));
+                }
+                if let Some(terminal) = other.terminal(x, y) {
+                    if self.terminal(x, y) != Some(terminal) {
+                        changes.terminals.push((x, y, terminal));
+                    }
+                }
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn compose() {
+        use CellState::*;
+        let a = ChangeSet {
+            changes: vec![(1, 1, LR), (0, 0, Blocked), (1, 1, UD)],
+            terminals: vec![],
+        };
+        let b = ChangeSet {
+            changes: vec![(0, 0, Empty), (2, 0, Blocked)],
+            terminals: vec![],
+        };
+        assert_eq!(
+            a.compose(&b).changes,
+            vec![(0, 0, Empty), (1, 1, UD), (2, 0, Blocked)]
+        );
+        assert_eq!(a.normalize().changes, vec![(0, 0, Blocked), (1, 1, UD)]);
+    }
+
+    #[test]
+    fn merge() {
+        use CellState::*;
+        let maze = Maze::new(5, 5);
+        let horizontal = maze.lee(0, 2, 4, 2).unwrap();
+        let vertical = maze.lee(2, 0, 2, 4).unwrap();
+        let merged = horizontal.merge(&vertical, &maze).unwrap();
+        let mut result = maze.clone();
+        result.apply(&merged);
+        assert_eq!(result.get(2, 2), Cross);
+        assert!(result.verify());
+
+        // sharing a terminal
+        let other = maze.lee(0, 2, 0, 4).unwrap();
+        assert!(!horizontal.conflicts_with(&other, &maze));
+        // running along the same cells
+        let other = maze.lee(1, 2, 3, 2).unwrap();
+        assert!(horizontal.conflicts_with(&other, &maze));
+        // ending on a wire
+        let other = maze.lee(2, 0, 2, 2).unwrap();
+        assert!(horizontal.conflicts_with(&other, &maze));
+
+        // crossing a wire that is already in the maze
+        let mut maze = maze.clone();
+        maze.apply(&horizontal);
+        let left = maze.lee(1, 0, 1, 4).unwrap();
+        let right = maze.lee(3, 0, 3, 4).unwrap();
+        let crossing = maze.lee(2, 0, 2, 4).unwrap();
+        assert!(!left.conflicts_with(&right, &maze));
+        assert!(!left.conflicts_with(&crossing, &maze));
+        assert!(crossing.conflicts_with(&crossing, &maze));
+    }
+
+    quickcheck! {
+        fn qc_diff(m: usize, n: usize, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            let mut maze = Maze::new(m, n);
+            let mut other = Maze::new(m, n);
+            for (i, (x1, y1, x2, y2)) in points.into_iter().enumerate() {
+                let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+                if i % 2 == 0 {
+                    maze.two_terminal_mut(x1, y1, x2, y2);
+                } else {
+                    other.two_terminal_mut(x1, y1, x2, y2);
+                }
+            }
+            let diff = maze.diff(&other);
+            maze.apply(&diff);
+            maze.to_string() == other.to_string() && maze.diff(&other).is_empty()
+        }
+
+        fn qc_merge(m: usize, n: usize, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            // routes computed against the same maze merge into a valid maze
+            let maze = Maze::new(m, n);
+            let mut merged = ChangeSet::default();
+            for (x1, y1, x2, y2) in points {
+                let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+                if let Some(changes) = maze.lee(x1, y1, x2, y2) {
+                    if let Some(result) = merged.merge(&changes, &maze) {
+                        merged = result;
+                    }
+                }
+            }
+            let mut result = maze.clone();
+            result.apply(&merged);
+            result.verify()
+        }
+    }
+}
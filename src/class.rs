@@ -0,0 +1,320 @@
+//! Net classes routing groups of nets the same way
+use super::*;
+use std::cmp::Reverse;
+
+/// A router a net class or `RouterChain` may use
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Lee,
+    LeeMinimumCrossing,
+    LeeMinimumEdgeEffect,
+    Hadlock,
+    MikamiTabuchi,
+    /// Single trunk Steiner tree, also for two-pin nets
+    SingleTrunk,
+}
+
+/// Routers of `Maze::two_terminal_with`, tried in order
+pub(crate) const TWO_PIN: [Algorithm; 3] =
+    [Algorithm::MikamiTabuchi, Algorithm::Hadlock, Algorithm::Lee];
+
+/// Routers of `Maze::multi_terminal_with`, tried in order
+pub(crate) const MULTI_PIN: [Algorithm; 3] = [
+    Algorithm::SingleTrunk,
+    Algorithm::MikamiTabuchi,
+    Algorithm::Lee,
+];
+
+/// A kind of net, e.g. power, clock or signal, whose nets are all routed
+/// the same way
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NetClass {
+    pub name: String,
+    /// Routers tried in order on two-pin nets, until one succeeds
+    pub two_pin: Vec<Algorithm>,
+    /// Routers tried in order on multi-pin nets. Lee routes them with
+    /// `Maze::lee_multi_with`, the other Lee variants and Hadlock do not
+    /// route them.
+    pub multi_pin: Vec<Algorithm>,
+    /// Pin recorded in the terminals of the nets
+    pub pin: u32,
+    /// Width and spacing, set as the rule of `pin` by
+    /// `Maze::route_netlist_mut` and kept for later routes and `Maze::drc`
+    pub rule: Rule,
+    /// Most wires a route may cross, `None` for any
+    pub max_crossings: Option<usize>,
+    pub weights: Weights,
+    /// Nets of classes with a higher priority are routed first
+    pub priority: i32,
+}
+
+impl NetClass {
+    /// Class routing like `two_terminal_with` and `multi_terminal_with`
+    pub fn new(name: &str) -> NetClass {
+        NetClass {
+            name: String::from(name),
+            two_pin: TWO_PIN.to_vec(),
+            multi_pin: MULTI_PIN.to_vec(),
+            pin: 0,
+            rule: Rule::default(),
+            max_crossings: None,
+            weights: Weights::default(),
+            priority: 0,
+        }
+    }
+
+    /// `options` with the weights, pin and crossings of this class
+    fn options(&self, options: &RouteOptions) -> RouteOptions {
+        RouteOptions {
+            planar: options.planar || self.max_crossings == Some(0),
+            weights: self.weights,
+            net: self.pin,
+            ..options.clone()
+        }
+    }
+}
+
+impl Default for NetClass {
+    fn default() -> NetClass {
+        NetClass::new("default")
+    }
+}
+
+/// A class or net that a `Netlist` does not accept
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetlistError {
+    /// No class has this name
+    UnknownClass(String),
+    /// Another class records its nets with this pin but has another rule
+    ConflictingRule(u32),
+}
+
+impl fmt::Display for NetlistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetlistError::UnknownClass(name) => write!(f, "unknown net class {}", name),
+            NetlistError::ConflictingRule(pin) => {
+                write!(f, "another class of pin {} has another rule", pin)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NetlistError {}
+
+/// Nets to be routed, each with a class
+#[derive(Clone, Default)]
+pub struct Netlist {
+    classes: Vec<NetClass>,
+    /// net and index of its class
+    nets: Vec<(Net, usize)>,
+}
+
+impl Netlist {
+    pub fn new() -> Netlist {
+        Netlist::default()
+    }
+
+    /// Add a class, replacing the class with the same name. The rule of a
+    /// pin is shared by all nets recording it, so classes of the same pin
+    /// must have the same rule.
+    pub fn add_class(&mut self, class: NetClass) -> Result<(), NetlistError> {
+        if self
+            .classes
+            .iter()
+            .any(|old| old.name != class.name && old.pin == class.pin && old.rule != class.rule)
+        {
+            return Err(NetlistError::ConflictingRule(class.pin));
+        }
+        match self.classes.iter_mut().find(|old| old.name == class.name) {
+            Some(old) => *old = class,
+            None => self.classes.push(class),
+        }
+        Ok(())
+    }
+
+    /// Add a net of the class named `class`
+    pub fn add(&mut self, net: Net, class: &str) -> Result<(), NetlistError> {
+        let index = self
+            .classes
+            .iter()
+            .position(|old| old.name == class)
+            .ok_or_else(|| NetlistError::UnknownClass(String::from(class)))?;
+        self.nets.push((net, index));
+        Ok(())
+    }
+
+    pub fn class(&self, name: &str) -> Option<&NetClass> {
+        self.classes.iter().find(|class| class.name == name)
+    }
+
+    /// Number of nets
+    pub fn len(&self) -> usize {
+        self.nets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nets.is_empty()
+    }
+}
+
+impl Maze {
+    /// Route a net as configured by `class`, trying its routers in order.
+    /// Each router gets the full budget of `options`, the error of the last
+    /// one is returned. The route follows the rule set for the pin of
+    /// `class`.
+    pub fn route_class_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        class: &NetClass,
+        net: &Net,
+    ) -> Result<ChangeSet, RouteError> {
+        let mut chain = RouterChain::from_algorithms(match net {
+            Net::TwoPin(..) => &class.two_pin,
+            Net::MultiPin(_) => &class.multi_pin,
+        });
+        chain.set_max_crossings(class.max_crossings);
+        let report = chain.route(self, ctx, &class.options(options), net)?;
+        Ok(report.changes)
+    }
+
+    /// Route the nets of `netlist` and apply the routes, the nets of
+    /// classes with a higher priority first and otherwise in order. The
+    /// rules of the classes are set first. Returns the result of every net
+    /// in the order of `netlist`.
+    pub fn route_netlist_mut(
+        &mut self,
+        options: &RouteOptions,
+        netlist: &Netlist,
+    ) -> Vec<Result<ChangeSet, RouteError>> {
+        for class in &netlist.classes {
+            self.set_rule(class.pin, class.rule);
+        }
+        let mut order: Vec<usize> = (0..netlist.nets.len()).collect();
+        order.sort_by_key(|i| Reverse(netlist.classes[netlist.nets[*i].1].priority));
+
+        let mut ctx = RouterContext::new(self);
+        let mut results = vec![Err(RouteError::Unroutable); netlist.nets.len()];
+        for i in order {
+            let (net, class) = &netlist.nets[i];
+            let result = self.route_class_with(&mut ctx, options, &netlist.classes[*class], net);
+            if let Ok(changes) = &result {
+                self.apply(changes);
+            }
+            results[i] = result;
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn net_classes() {
+        let mut power = NetClass::new("power");
+        power.priority = 1;
+        let mut signal = NetClass::new("signal");
+        signal.two_pin = vec![Algorithm::Lee];
+        signal.max_crossings = Some(0);
+        let mut netlist = Netlist::new();
+        netlist.add_class(power).unwrap();
+        netlist.add_class(signal).unwrap();
+        // the power net is routed first and cuts the signal net
+        netlist.add(Net::TwoPin(0, 1, 4, 1), "signal").unwrap();
+        netlist.add(Net::TwoPin(2, 0, 2, 2), "power").unwrap();
+        let mut maze = Maze::new(5, 3);
+        let results = maze.route_netlist_mut(&RouteOptions::default(), &netlist);
+        assert_eq!(results[0].as_ref().err(), Some(&RouteError::Unroutable));
+        assert!(results[1].is_ok());
+        assert_eq!(maze.get(2, 1), CellState::UD);
+
+        // without the crossing limit the signal net crosses
+        let mut signal = netlist.class("signal").unwrap().clone();
+        signal.max_crossings = None;
+        netlist.add_class(signal).unwrap();
+        let mut maze = Maze::new(5, 3);
+        let results = maze.route_netlist_mut(&RouteOptions::default(), &netlist);
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert_eq!(maze.get(2, 1), CellState::Cross);
+        assert!(maze.verify());
+
+        // a class may not change the rule of a pin of another class
+        let mut wide = NetClass::new("wide");
+        wide.rule = Rule::new(3, 0);
+        assert_eq!(
+            netlist.add_class(wide.clone()),
+            Err(NetlistError::ConflictingRule(0))
+        );
+        wide.pin = 1;
+        assert!(netlist.add_class(wide).is_ok());
+        assert_eq!(
+            netlist.add(Net::TwoPin(0, 0, 1, 1), "clock"),
+            Err(NetlistError::UnknownClass(String::from("clock")))
+        );
+        assert_eq!(netlist.len(), 2);
+    }
+
+    #[test]
+    fn weights() {
+        let mut maze = Maze::new(7, 5);
+        maze.two_terminal_mut(3, 0, 3, 3);
+        let mut ctx = RouterContext::new(&maze);
+        let mut class = NetClass::new("signal");
+        class.two_pin = vec![Algorithm::LeeMinimumCrossing];
+        let net = Net::TwoPin(0, 0, 6, 0);
+        // around the wire
+        let changes = maze
+            .route_class_with(&mut ctx, &RouteOptions::default(), &class, &net)
+            .unwrap();
+        assert_eq!(changes.crossings(), 0);
+        assert_eq!(changes.len(), 15);
+
+        // crossing costs less than going around
+        class.weights = Weights {
+            length: 1,
+            crossing: 5,
+            edge: 0,
+            coupling: 0,
+        };
+        let changes = maze
+            .route_class_with(&mut ctx, &RouteOptions::default(), &class, &net)
+            .unwrap();
+        assert_eq!(changes.crossings(), 1);
+        assert!(changes.len() < 15);
+    }
+
+    quickcheck! {
+        fn qc_default_class(m: usize, n: usize, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            // the default class routes like two_terminal and multi_terminal
+            let mut netlist = Netlist::new();
+            netlist.add_class(NetClass::default()).unwrap();
+            let mut maze = Maze::new(m, n);
+            let mut expected = Maze::new(m, n);
+            for (i, (x1, y1, x2, y2)) in points.into_iter().enumerate() {
+                let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+                if i % 2 == 0 {
+                    netlist.add(Net::TwoPin(x1, y1, x2, y2), "default").unwrap();
+                    expected.two_terminal_mut(x1, y1, x2, y2);
+                } else {
+                    let points = Points::new(&[(x1, y1), (x2, y2), (x1, y2)]);
+                    expected.multi_terminal_mut(&points);
+                    netlist.add(Net::MultiPin(points), "default").unwrap();
+                }
+            }
+            maze.route_netlist_mut(&RouteOptions::default(), &netlist);
+            maze.verify() && maze.to_string() == expected.to_string()
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! Removing unnecessary bends from routes
+use super::*;
+use std::collections::BTreeSet;
+
+#[wasm_bindgen]
+impl Maze {
+    /// Straighten a two-pin route computed against this maze: replace
+    /// staircases and jogs between two bends by an L-shape where the cells
+    /// are free, as long as the route gets neither longer nor crosses more
+    /// wires, and keeps the width and spacing of its net. Other routes are
+    /// returned unchanged.
+    pub fn straighten(&self, changes: &ChangeSet) -> ChangeSet {
+        let mut path = match self.route_path(changes) {
+            Some(path) => path,
+            None => return changes.clone(),
+        };
+        let pins: Vec<u32> = changes.terminals.iter().map(|(_, _, t)| t.pin).collect();
+        let keep_out = self.keep_out(&pins, &[path[0], path[path.len() - 1]]);
+        let keep_out = keep_out.as_ref().map(|(_, keep_out)| keep_out);
+        while let Some(better) = self.straighten_once(&path, keep_out) {
+            path = better;
+        }
+        self.path_changes(changes, &path)
+    }
+}
+
+impl Maze {
+    /// Cells of a two-pin route from one terminal to the other
+    pub(crate) fn route_path(&self, changes: &ChangeSet) -> Option<Vec<(usize, usize)>> {
+        let states = changes.final_states();
+        let terminals: BTreeMap<(usize, usize), Terminal> = changes
+            .terminals
+            .iter()
+            .map(|(x, y, terminal)| ((*x, *y), *terminal))
+            .collect();
+        // sides used by the route in every cell
+        let mut sides = BTreeMap::new();
+        for ((x, y), state) in &states {
+            let new = match (state, terminals.get(&(*x, *y))) {
+                (CellState::Terminal, Some(terminal)) => terminal.directions(),
+                _ => state.extract(),
+            };
+            let old = self.directions(*x, *y);
+            let mut added = [false; 4];
+            for i in 0..4 {
+                added[i] = new[i] && !old[i];
+            }
+            sides.insert((*x, *y), added);
+        }
+
+        let ends: Vec<(usize, usize)> = states
+            .iter()
+            .filter(|(_, state)| **state == CellState::Terminal)
+            .map(|(cell, _)| *cell)
+            .collect();
+        if ends.len() != 2 || sides[&ends[0]].iter().filter(|side| **side).count() != 1 {
+            return None;
+        }
+        let mut path = vec![ends[0]];
+        let mut from = None;
+        let (mut x, mut y) = ends[0];
+        while path.len() == 1 || (x, y) != ends[1] {
+            let direction = [Direction::L, Direction::R, Direction::U, Direction::D]
+                .iter()
+                .copied()
+                .find(|direction| sides[&(x, y)][direction.index()] && Some(*direction) != from)?;
+            let next = self.map.neighbor(x, y, direction)?;
+            if !sides.contains_key(&next) || path.contains(&next) {
+                return None;
+            }
+            path.push(next);
+            from = Some(direction.opposite());
+            x = next.0;
+            y = next.1;
+        }
+        if path.len() == states.len() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Route along `path`, keeping the pins of the terminals of `changes`
+    pub(crate) fn path_changes(&self, changes: &ChangeSet, path: &[(usize, usize)]) -> ChangeSet {
+        let terminal = |(x, y): (usize, usize), side: Direction| {
+            let mut terminal = self.terminal(x, y).unwrap_or_default();
+            if let Some((_, _, new)) = changes
+                .terminals
+                .iter()
+                .find(|(tx, ty, _)| (*tx, *ty) == (x, y))
+            {
+                terminal.pin = new.pin;
+            }
+            terminal.connect(side);
+            (x, y, terminal)
+        };
+
+        let (first, last) = (path[0], path[path.len() - 1]);
+        let mut result = ChangeSet::default();
+        result.changes.push((first.0, first.1, CellState::Terminal));
+        result.changes.push((last.0, last.1, CellState::Terminal));
+        result
+            .terminals
+            .push(terminal(first, direction(first, path[1])));
+        result
+            .terminals
+            .push(terminal(last, direction(last, path[path.len() - 2])));
+        for i in 1..path.len() - 1 {
+            let (x, y) = path[i];
+            let into = direction(path[i - 1], path[i]);
+            let out = direction(path[i], path[i + 1]);
+            result
+                .changes
+                .push((x, y, out.get_new_cell_state(&into, &self.map[(x, y)])));
+        }
+        result
+    }
+
+    /// Replace the part of `path` between two bends by an L-shape, if that
+    /// makes it shorter or bend less without crossing more wires
+    fn straighten_once(
+        &self,
+        path: &[(usize, usize)],
+        keep_out: Option<&Grid<bool>>,
+    ) -> Option<Vec<(usize, usize)>> {
+        // the ends and every cell where the path turns
+        let mut corners = vec![0];
+        for i in 1..path.len() - 1 {
+            let (a, b, c) = (path[i - 1], path[i], path[i + 1]);
+            if (a.0 == b.0) != (b.0 == c.0) {
+                corners.push(i);
+            }
+        }
+        corners.push(path.len() - 1);
+
+        let score = |path: &[(usize, usize)]| (path.len(), bends(path));
+        let old_score = score(path);
+        for (k, i) in corners.iter().enumerate() {
+            for j in corners.iter().skip(k + 2).rev() {
+                let (start, end) = (path[*i], path[*j]);
+                let old_crossings = self.path_crossings(&path[i + 1..*j]);
+                let mut used: BTreeSet<(usize, usize)> = path[..*i].iter().copied().collect();
+                used.extend(path[j + 1..].iter().copied());
+                for corner in &[(end.0, start.1), (start.0, end.1)] {
+                    let middle = match self.free_line(start, *corner, end, &used, keep_out) {
+                        Some(middle) => middle,
+                        None => continue,
+                    };
+                    if self.path_crossings(&middle) > old_crossings {
+                        continue;
+                    }
+                    let mut new_path = path[..=*i].to_vec();
+                    new_path.extend(middle);
+                    new_path.extend_from_slice(&path[*j..]);
+                    if score(&new_path) < old_score {
+                        return Some(new_path);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Cells strictly between `start` and `end` going straight through
+    /// `corner`, if a wire can run there
+    fn free_line(
+        &self,
+        start: (usize, usize),
+        corner: (usize, usize),
+        end: (usize, usize),
+        used: &BTreeSet<(usize, usize)>,
+        keep_out: Option<&Grid<bool>>,
+    ) -> Option<Vec<(usize, usize)>> {
+        let mut cells = vec![];
+        let (mut x, mut y) = start;
+        for target in &[corner, end] {
+            while (x, y) != *target {
+                let direction = if x < target.0 {
+                    Direction::R
+                } else if x > target.0 {
+                    Direction::L
+                } else if y < target.1 {
+                    Direction::U
+                } else {
+                    Direction::D
+                };
+                let (new_x, new_y) = self.map.neighbor(x, y, direction)?;
+                if !self.can_connect(x, y, new_x, new_y, direction)
+                    || used.contains(&(new_x, new_y))
+                    || keep_out.is_some_and(|keep_out| keep_out[(new_x, new_y)])
+                {
+                    return None;
+                }
+                if (new_x, new_y) != end
+                    && !direction.can_cross(&self.map[(new_x, new_y)], self.planar)
+                {
+                    return None;
+                }
+                cells.push((new_x, new_y));
+                x = new_x;
+                y = new_y;
+            }
+        }
+        cells.pop();
+        // crossing cells turn no corner and are not entered twice
+        let mut seen = BTreeSet::new();
+        for (i, cell) in cells.iter().enumerate() {
+            if !seen.insert(*cell) {
+                return None;
+            }
+            let turns = i > 0
+                && i + 1 < cells.len()
+                && (cells[i - 1].0 == cell.0) != (cell.0 == cells[i + 1].0);
+            if self.map[*cell] != CellState::Empty && (turns || *cell == corner) {
+                return None;
+            }
+        }
+        Some(cells)
+    }
+
+    /// Cells of `cells` where the route crosses a wire
+    fn path_crossings(&self, cells: &[(usize, usize)]) -> usize {
+        cells
+            .iter()
+            .filter(|cell| self.map[**cell] != CellState::Empty)
+            .count()
+    }
+}
+
+/// Direction from a cell to the adjacent cell `to`
+pub(crate) fn direction(from: (usize, usize), to: (usize, usize)) -> Direction {
+    if to.0 < from.0 {
+        Direction::L
+    } else if to.0 > from.0 {
+        Direction::R
+    } else if to.1 > from.1 {
+        Direction::U
+    } else {
+        Direction::D
+    }
+}
+
+/// Number of turns along `path`
+fn bends(path: &[(usize, usize)]) -> usize {
+    path.windows(3)
+        .filter(|w| (w[0].0 == w[1].0) != (w[1].0 == w[2].0))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn straighten() {
+        let maze = Maze::new(5, 5);
+        let staircase = [
+            (0, 0),
+            (1, 0),
+            (1, 1),
+            (2, 1),
+            (2, 2),
+            (3, 2),
+            (3, 3),
+            (4, 3),
+            (4, 4),
+        ];
+        let changes = maze.path_changes(&ChangeSet::default(), &staircase);
+        let straight = maze.straighten(&changes);
+        let mut result = maze.clone();
+        result.apply(&straight);
+        println!("{}", result);
+        assert!(result.verify());
+        assert_eq!(straight.len(), changes.len());
+        assert_eq!(result.to_string(), "....╻\n....┃\n....┃\n....┃\n╺━━━┛\n");
+
+        // only the middle of the staircase is straightened past the blocks
+        let maze = maze.fill(2, 0, 4, 0).fill(0, 2, 0, 4);
+        let straight = maze.straighten(&changes);
+        let mut result = maze.clone();
+        result.apply(&straight);
+        println!("{}", result);
+        assert!(result.verify());
+        assert_eq!(straight.len(), changes.len());
+        let path = maze.route_path(&straight).unwrap();
+        assert_eq!(bends(&staircase), 7);
+        assert_eq!(bends(&path), 2);
+    }
+
+    quickcheck! {
+        fn qc_straighten(m: usize, n: usize, blocks: Vec<(usize, usize)>, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 30 || n > 30 {
+                return true
+            }
+
+            let mut maze = Maze::new(m, n);
+            for (x, y) in blocks {
+                maze.fill_mut(x % m, y % n, x % m, y % n);
+            }
+            for (x1, y1, x2, y2) in points {
+                let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+                let changes = match maze.lee(x1, y1, x2, y2) {
+                    Some(changes) => changes,
+                    None => continue,
+                };
+                let straight = maze.straighten(&changes);
+                if straight.len() > changes.len() || straight.crossings() > changes.crossings() {
+                    return false;
+                }
+                maze.apply(&straight);
+                if !maze.verify() {
+                    return false;
+                }
+            }
+            true
+        }
+    }
+}
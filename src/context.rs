@@ -0,0 +1,326 @@
+//! Reusable scratch buffers for routers
+use super::*;
+use crate::hadlock::HadlockCrossingState;
+use crate::lee::LeeCostState;
+use crate::options::{Budget, Rect, Ties};
+
+/// Largest generation that fits next to the source flag and a direction in
+/// a visited mark
+const MAX_GENERATION: u16 = (1 << 13) - 1;
+
+/// Bit of a visited mark set for the sources of a search
+const SOURCE: u16 = 1 << 2;
+
+/// Scratch state for routing many nets on mazes of the same size.
+///
+/// Visited marks are stamped with a generation counter, so starting a new
+/// search costs O(1) instead of clearing the whole grid. The multi-terminal
+/// routers write their partial tree into an overlay stamped the same way
+/// instead of cloning the maze.
+pub struct RouterContext {
+    m: usize,
+    n: usize,
+    generation: u16,
+    /// generation in which a cell was last visited in the high 13 bits,
+    /// whether it is a source of that search (`SOURCE`) and the direction
+    /// back to its parent in the low 2 bits
+    marks: Grid<u16>,
+    /// working copy of cells changed by the current search with the
+    /// generation in which each was last written, allocated on first use
+    /// since only the multi-terminal routers need it
+    overlay: Option<(Grid<u16>, Grid<CellState>)>,
+    /// cells the current search may enter
+    pub(crate) window: Rect,
+    /// fail instead of growing the window beyond the first one
+    pub(crate) confined: bool,
+    /// a confined search needed more than its first window
+    pub(crate) escaped: bool,
+    /// the current net may not cross wires
+    pub(crate) planar: bool,
+    /// cells the current net may not enter to keep its width and spacing,
+    /// `None` if the maze has no rules
+    pub(crate) keep_out: Option<Grid<bool>>,
+    /// neighbouring wires of aggressor nets of every cell, `None` unless
+    /// coupling is weighted
+    pub(crate) coupling: Option<Grid<u8>>,
+    budget: Budget,
+    ties: Ties,
+    pub(crate) queue: VecDeque<(u32, u32)>,
+    pub(crate) line_queue: VecDeque<(u32, u32, Direction)>,
+    pub(crate) cost_heap: BinaryHeap<LeeCostState>,
+    pub(crate) hadlock_heap: BinaryHeap<HadlockCrossingState>,
+}
+
+impl RouterContext {
+    /// Create scratch buffers for mazes with the size of `maze`
+    pub fn new(maze: &Maze) -> RouterContext {
+        RouterContext {
+            m: maze.m,
+            n: maze.n,
+            generation: 0,
+            marks: Grid::new(maze.m, maze.n, 0),
+            overlay: None,
+            window: Rect::full(maze.m, maze.n),
+            confined: false,
+            escaped: false,
+            planar: false,
+            keep_out: None,
+            coupling: None,
+            budget: Budget::default(),
+            ties: Ties::default(),
+            queue: VecDeque::new(),
+            line_queue: VecDeque::new(),
+            cost_heap: BinaryHeap::new(),
+            hadlock_heap: BinaryHeap::new(),
+        }
+    }
+
+    /// Prepare for routing one net on `maze` within the budget of `options`
+    pub(crate) fn start(&mut self, maze: &Maze, options: &RouteOptions) {
+        if self.m != maze.m || self.n != maze.n {
+            *self = RouterContext::new(maze);
+        }
+        self.window = Rect::full(maze.m, maze.n);
+        self.budget = Budget::new(options);
+        self.ties = Ties::new(options);
+        self.planar = options.planar || maze.planar;
+        self.keep_out = None;
+        self.coupling = None;
+    }
+
+    /// Start a new search, forgetting the previous one
+    pub(crate) fn begin(&mut self) {
+        if self.generation == MAX_GENERATION {
+            self.marks.fill(0);
+            if let Some((written, _)) = &mut self.overlay {
+                written.fill(0);
+            }
+            self.generation = 0;
+        }
+        self.generation += 1;
+        self.queue.clear();
+        self.line_queue.clear();
+        self.cost_heap.clear();
+        self.hadlock_heap.clear();
+    }
+
+    /// Count one expanded cell against the budget
+    #[inline]
+    pub(crate) fn expand(&mut self) -> Result<(), RouteError> {
+        self.budget.expand()
+    }
+
+    /// Neighbour of (x, y) in `direction`, if it is inside the window and
+    /// not kept out
+    #[inline]
+    pub(crate) fn neighbor(
+        &self,
+        x: usize,
+        y: usize,
+        direction: Direction,
+    ) -> Option<(usize, usize)> {
+        use Direction::*;
+        let window = &self.window;
+        let neighbor = match direction {
+            L if x > window.x1 => Some((x - 1, y)),
+            R if x < window.x2 => Some((x + 1, y)),
+            D if y > window.y1 => Some((x, y - 1)),
+            U if y < window.y2 => Some((x, y + 1)),
+            _ => None,
+        };
+        match &self.keep_out {
+            Some(keep_out) => neighbor.filter(|cell| !keep_out[*cell]),
+            None => neighbor,
+        }
+    }
+
+    /// Number of wires of aggressor nets next to (x, y)
+    #[inline]
+    pub(crate) fn coupled(&self, x: usize, y: usize) -> usize {
+        self.coupling
+            .as_ref()
+            .map_or(0, |coupling| coupling[(x, y)] as usize)
+    }
+
+    /// Whether (x, y) has been reached by the current search
+    #[inline]
+    pub(crate) fn visited(&self, x: usize, y: usize) -> bool {
+        self.marks[(x, y)] >> 3 == self.generation
+    }
+
+    /// Mark (x, y) reached, remembering the direction back to its parent
+    #[inline]
+    pub(crate) fn visit(&mut self, x: usize, y: usize, direction: Direction) {
+        let source = if self.visited(x, y) {
+            self.marks[(x, y)] & SOURCE
+        } else {
+            0
+        };
+        self.marks[(x, y)] = self.generation << 3 | source | direction.index() as u16;
+    }
+
+    /// Mark (x, y) as a start of the current search
+    #[inline]
+    pub(crate) fn visit_source(&mut self, x: usize, y: usize) {
+        self.marks[(x, y)] = self.generation << 3 | SOURCE;
+    }
+
+    /// Whether the current search started from (x, y)
+    #[inline]
+    pub(crate) fn is_source(&self, x: usize, y: usize) -> bool {
+        self.visited(x, y) && self.marks[(x, y)] & SOURCE != 0
+    }
+
+    /// Order to expand the neighbours of the visited cell (x, y)
+    #[inline]
+    pub(crate) fn order(&mut self, x: usize, y: usize) -> [Direction; 4] {
+        let travel = if self.is_source(x, y) {
+            None
+        } else {
+            Some(self.direction(x, y).opposite())
+        };
+        self.ties.order(travel)
+    }
+
+    /// Key ordering (x, y) among cells of equal cost, the smallest first
+    #[inline]
+    pub(crate) fn key(&mut self, x: usize, y: usize) -> u64 {
+        self.ties.key(x, y)
+    }
+
+    /// Direction back to the parent of a visited cell
+    #[inline]
+    pub(crate) fn direction(&self, x: usize, y: usize) -> Direction {
+        debug_assert!(self.visited(x, y));
+        Direction::from_index(self.marks[(x, y)] as usize & 3)
+    }
+
+    /// State of (x, y) in `maze` with the changes of the current search applied
+    #[inline]
+    pub(crate) fn cell(&self, maze: &Maze, x: usize, y: usize) -> CellState {
+        match &self.overlay {
+            Some((written, overlay)) if written[(x, y)] == self.generation => overlay[(x, y)],
+            _ => maze.map[(x, y)],
+        }
+    }
+
+    /// Record a change of (x, y) made by the current search
+    #[inline]
+    pub(crate) fn write(&mut self, x: usize, y: usize, state: CellState) {
+        let (m, n) = (self.m, self.n);
+        let (written, overlay) = self
+            .overlay
+            .get_or_insert_with(|| (Grid::new(m, n, 0), Grid::new(m, n, CellState::Empty)));
+        written[(x, y)] = self.generation;
+        overlay[(x, y)] = state;
+    }
+
+    /// Walk back from the target (x2, y2) to a source along the recorded
+    /// directions, returning the path and its terminals
+    pub(crate) fn path(&self, maze: &Maze, x2: usize, y2: usize) -> ChangeSet {
+        let mut changes = vec![];
+        let mut terminals = Terminals::default();
+        terminals.add(maze, &mut changes, x2, y2);
+        if self.is_source(x2, y2) {
+            return terminals.finish(changes);
+        }
+        let mut direction = self.direction(x2, y2);
+        terminals.connect(x2, y2, direction);
+        let mut cur_x = x2;
+        let mut cur_y = y2;
+        loop {
+            let (dx, dy) = direction.offset();
+            let new_x = (cur_x as isize + dx) as usize;
+            let new_y = (cur_y as isize + dy) as usize;
+            if self.is_source(new_x, new_y) {
+                terminals.add(maze, &mut changes, new_x, new_y);
+                terminals.connect(new_x, new_y, direction.opposite());
+                break;
+            }
+
+            let new_direction = self.direction(new_x, new_y);
+            changes.push((
+                new_x,
+                new_y,
+                new_direction.get_new_cell_state(&direction, &maze.map[(new_x, new_y)]),
+            ));
+            cur_x = new_x;
+            cur_y = new_y;
+            direction = new_direction;
+        }
+        terminals.finish(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn context_reuse() {
+        let mut ctx = RouterContext::new(&Maze::new(1, 1));
+        let mut maze = Maze::new(5, 5);
+        let options = RouteOptions::default();
+        let points = Points::new(&[(0, 0), (4, 4), (0, 4)]);
+        let changes = maze.lee_multi_with(&mut ctx, &options, &points);
+        maze.apply(&changes.unwrap());
+        // the overlay of the previous search must not leak into this one
+        assert!(maze.lee_with(&mut ctx, &options, 1, 3, 3, 1).is_ok());
+        assert!(maze
+            .mikami_tabuchi_with(&mut ctx, &options, 1, 1, 3, 3)
+            .is_ok());
+        assert!(maze.verify());
+        println!("{}", maze);
+    }
+
+    #[test]
+    fn context_overlay_on_first_use() {
+        let maze = Maze::new(5, 5);
+        let mut ctx = RouterContext::new(&maze);
+        let options = RouteOptions::default();
+        assert!(maze.lee_with(&mut ctx, &options, 0, 0, 4, 4).is_ok());
+        assert!(maze.hadlock_with(&mut ctx, &options, 0, 0, 4, 4).is_ok());
+        assert!(ctx.overlay.is_none());
+        let points = Points::new(&[(0, 0), (4, 4), (0, 4)]);
+        assert!(maze.lee_multi_with(&mut ctx, &options, &points).is_ok());
+        assert!(ctx.overlay.is_some());
+    }
+
+    #[test]
+    fn context_generation_wraps() {
+        let maze = Maze::new(3, 3);
+        let mut ctx = RouterContext::new(&maze);
+        let options = RouteOptions::default();
+        assert!(maze.lee_with(&mut ctx, &options, 0, 0, 2, 2).is_ok());
+        ctx.generation = MAX_GENERATION;
+        assert!(maze.lee_with(&mut ctx, &options, 0, 0, 2, 2).is_ok());
+        assert_eq!(ctx.generation, 1);
+        assert!(maze.lee_with(&mut ctx, &options, 0, 2, 2, 0).is_ok());
+    }
+
+    quickcheck! {
+        fn qc_context_same_as_fresh(m: usize, n: usize, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            let mut ctx = RouterContext::new(&Maze::new(m, n));
+            let mut maze = Maze::new(m, n);
+            let mut fresh = Maze::new(m, n);
+            for (x1, y1, x2, y2) in points {
+                let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+                let options = RouteOptions::default();
+                if let Ok(report) = maze.two_terminal_with(&mut ctx, &options, x1, y1, x2, y2) {
+                    maze.apply(&report.changes);
+                }
+                fresh.two_terminal_mut(x1, y1, x2, y2);
+            }
+            maze.to_string() == fresh.to_string()
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Crosstalk between wires of different nets running side by side
+use super::*;
+use std::cmp::Reverse;
+
+/// Two nets running side by side, found by `Maze::parallel_runs`. A net is
+/// named by its smallest cell that is not a crossing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParallelRun {
+    pub first: (usize, usize),
+    pub second: (usize, usize),
+    /// pins of the terminals of either net, sorted and each once
+    pub pins: (Vec<u32>, Vec<u32>),
+    /// cells where straight wires of the two nets are next to each other
+    pub length: usize,
+    /// most such cells in a row
+    pub longest: usize,
+}
+
+impl Maze {
+    /// Every pair of nets with straight wires in adjacent cells, running
+    /// the same way, sorted by `length` and then by net
+    pub fn parallel_runs(&self) -> Vec<ParallelRun> {
+        let nets = self.nets();
+        // net of the horizontal and of the vertical straight wire of every
+        // cell
+        let mut horizontal = Grid::new(self.m, self.n, None);
+        let mut vertical = Grid::new(self.m, self.n, None);
+        for (i, net) in nets.iter().enumerate() {
+            for (cell, part) in net.cells.iter().zip(&net.parts) {
+                match (self.map[*cell], part) {
+                    (CellState::LR, _) | (CellState::Cross, 1) => horizontal[*cell] = Some(i),
+                    (CellState::UD, _) | (CellState::Cross, 2) => vertical[*cell] = Some(i),
+                    _ => {}
+                }
+            }
+        }
+
+        // length and longest run of every pair of nets, with the current
+        // run of each kind
+        let mut pairs: BTreeMap<(usize, usize), (usize, usize)> = BTreeMap::new();
+        let mut count =
+            |a: Option<usize>, b: Option<usize>, run: &mut Option<(usize, usize, usize)>| match (
+                a, b,
+            ) {
+                (Some(a), Some(b)) if a != b => {
+                    let pair = (min(a, b), max(a, b));
+                    let length = match run {
+                        Some((first, second, length)) if (*first, *second) == pair => *length + 1,
+                        _ => 1,
+                    };
+                    *run = Some((pair.0, pair.1, length));
+                    let entry = pairs.entry(pair).or_insert((0, 0));
+                    entry.0 += 1;
+                    entry.1 = max(entry.1, length);
+                }
+                _ => *run = None,
+            };
+        // horizontal wires one above the other
+        for y in 0..self.n.saturating_sub(1) {
+            let mut run = None;
+            for x in 0..self.m {
+                count(horizontal[(x, y)], horizontal[(x, y + 1)], &mut run);
+            }
+        }
+        // vertical wires side by side
+        for x in 0..self.m.saturating_sub(1) {
+            let mut run = None;
+            for y in 0..self.n {
+                count(vertical[(x, y)], vertical[(x + 1, y)], &mut run);
+            }
+        }
+
+        // nets may share crossings, but never their other cells
+        let name = |i: usize| {
+            *nets[i]
+                .cells
+                .iter()
+                .filter(|cell| self.map[**cell] != CellState::Cross)
+                .min()
+                .unwrap()
+        };
+        let mut runs: Vec<ParallelRun> = pairs
+            .into_iter()
+            .map(|((a, b), (length, longest))| {
+                let (a, b) = if name(a) < name(b) { (a, b) } else { (b, a) };
+                ParallelRun {
+                    first: name(a),
+                    second: name(b),
+                    pins: (nets[a].pins.clone(), nets[b].pins.clone()),
+                    length,
+                    longest,
+                }
+            })
+            .collect();
+        runs.sort_by_key(|run| (Reverse(run.length), run.first, run.second));
+        runs
+    }
+
+    /// Number of wires of aggressor nets next to every cell, for a net
+    /// joining `terminals`. Aggressors are the nets with a pin in
+    /// `aggressors`, or all others if it is empty.
+    pub(crate) fn coupling(&self, terminals: &[(usize, usize)], aggressors: &[u32]) -> Grid<u8> {
+        use Direction::*;
+        let mut coupling = Grid::new(self.m, self.n, 0u8);
+        for net in self.nets() {
+            if net.cells.iter().any(|cell| terminals.contains(cell))
+                || !aggressors.is_empty() && !net.pins.iter().any(|pin| aggressors.contains(pin))
+            {
+                continue;
+            }
+            for (x, y) in &net.cells {
+                for direction in &[L, R, U, D] {
+                    if let Some(neighbor) = self.map.neighbor(*x, *y, *direction) {
+                        coupling[neighbor] = coupling[neighbor].saturating_add(1);
+                    }
+                }
+            }
+        }
+        coupling
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn parallel_runs() {
+        let mut maze = Maze::new(8, 5);
+        for (pin, (x1, y1, x2, y2)) in [(0, 0, 7, 0), (2, 1, 5, 1), (0, 3, 7, 3)]
+            .iter()
+            .enumerate()
+        {
+            let mut changes = maze.two_terminal(*x1, *y1, *x2, *y2).unwrap();
+            changes.set_pin(pin as u32 + 1);
+            maze.apply(&changes);
+        }
+        let runs = maze.parallel_runs();
+        println!("{}\n{:?}", maze, runs);
+        assert_eq!(
+            runs,
+            vec![ParallelRun {
+                first: (0, 0),
+                second: (2, 1),
+                pins: (vec![1], vec![2]),
+                length: 2,
+                longest: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn coupling_cost() {
+        let mut maze = Maze::new(8, 4);
+        maze.two_terminal_mut(0, 1, 7, 1);
+        let mut ctx = RouterContext::new(&maze);
+        let options = RouteOptions::default();
+        // the shortest route runs along the wire
+        let changes = maze
+            .lee_minimum_edge_effect_with(&mut ctx, &options, 1, 2, 6, 2)
+            .unwrap();
+        let mut routed = maze.clone();
+        routed.apply(&changes);
+        assert_eq!(routed.parallel_runs()[0].length, 4);
+
+        // keeping a row away costs two steps
+        let options = RouteOptions {
+            weights: Weights {
+                length: 1,
+                coupling: 5,
+                ..Weights::default()
+            },
+            ..options
+        };
+        let changes = maze
+            .lee_minimum_edge_effect_with(&mut ctx, &options, 1, 2, 6, 2)
+            .unwrap();
+        let mut routed = maze.clone();
+        routed.apply(&changes);
+        println!("{}", routed);
+        assert!(routed.verify());
+        assert_eq!(changes.len(), 8);
+        assert!(routed.parallel_runs().is_empty());
+
+        // only wires of the aggressor count
+        let options = RouteOptions {
+            aggressors: vec![3],
+            ..options
+        };
+        let changes = maze
+            .lee_minimum_edge_effect_with(&mut ctx, &options, 1, 2, 6, 2)
+            .unwrap();
+        assert_eq!(changes.len(), 6);
+    }
+
+    quickcheck! {
+        fn qc_parallel_runs(m: usize, n: usize, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 30 || n > 30 {
+                return true
+            }
+
+            let mut maze = Maze::new(m, n);
+            for (x1, y1, x2, y2) in points {
+                maze.lee_mut(x1 % m, y1 % n, x2 % m, y2 % n);
+            }
+            let runs = maze.parallel_runs();
+            let mut pairs: Vec<_> = runs.iter().map(|run| (run.first, run.second)).collect();
+            pairs.sort_unstable();
+            pairs.dedup();
+            pairs.len() == runs.len()
+                && runs.windows(2).all(|w| w[0].length >= w[1].length)
+                && runs.iter().all(|run| run.longest > 0 && run.longest <= run.length && run.first < run.second)
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! Lowering the crossings of many nets by rip-up and reroute
+use super::*;
+use std::cmp::Reverse;
+
+/// Outcome of `Maze::route_minimizing_crossings_mut`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrossingReport {
+    /// index and reason of every net that was not routed
+    pub failed: Vec<(usize, RouteError)>,
+    /// crossings after routing every net once
+    pub before: usize,
+    /// crossings after rerouting
+    pub after: usize,
+    /// index of every net whose new route was kept, in order
+    pub rerouted: Vec<usize>,
+}
+
+/// A net as routed in the maze
+pub(crate) struct Routed {
+    index: usize,
+    /// directions (L, R, U, D) added by this net to each cell it uses
+    wires: Vec<(usize, usize, [bool; 4])>,
+}
+
+impl Maze {
+    /// Number of cells where two wires cross
+    pub fn crossings(&self) -> usize {
+        let mut crossings = 0;
+        for x in 0..self.m {
+            for y in 0..self.n {
+                crossings += (self.map[(x, y)] == CellState::Cross) as usize;
+            }
+        }
+        crossings
+    }
+
+    /// Route `nets` in order with `route_with`, then repeatedly rip up the
+    /// net with the most crossings and reroute it on the remaining board,
+    /// two-pin nets with `lee_minimum_crossing_with` and multi-pin nets with
+    /// `lee_multi_with`, without crossings if it can. A new route is
+    /// kept only if it has fewer crossings than the old one, so the total
+    /// never grows. Stops after `rounds` rounds over all nets, or earlier
+    /// when a round keeps no new route.
+    pub fn route_minimizing_crossings_mut(
+        &mut self,
+        options: &RouteOptions,
+        nets: &[Net],
+        rounds: usize,
+    ) -> CrossingReport {
+        let mut ctx = RouterContext::new(self);
+        let mut routed = vec![];
+        let mut failed = vec![];
+        for (index, net) in nets.iter().enumerate() {
+            match self.route_with(&mut ctx, options, net) {
+                Ok(changes) => routed.push(self.apply_net(index, &changes)),
+                Err(err) => failed.push((index, err)),
+            }
+        }
+
+        let before = self.crossings();
+        let mut rerouted = vec![];
+        for _ in 0..rounds {
+            // the most crossings first
+            let mut order: Vec<(usize, usize)> = routed
+                .iter()
+                .enumerate()
+                .map(|(i, net)| (self.net_crossings(net), i))
+                .filter(|(crossings, _)| *crossings > 0)
+                .collect();
+            order.sort_by_key(|(crossings, i)| (Reverse(*crossings), *i));
+
+            let mut improved = false;
+            for (_, i) in order {
+                // the crossings of a net may have changed since sorting
+                let old = self.net_crossings(&routed[i]);
+                let restore = self.apply(&self.rip_up(&routed[i]));
+                let result = match &nets[routed[i].index] {
+                    Net::TwoPin(x1, y1, x2, y2) => {
+                        self.lee_minimum_crossing_with(&mut ctx, options, *x1, *y1, *x2, *y2)
+                    }
+                    Net::MultiPin(points) => {
+                        let planar = RouteOptions {
+                            planar: true,
+                            ..options.clone()
+                        };
+                        self.lee_multi_with(&mut ctx, &planar, points)
+                            .or_else(|_| self.lee_multi_with(&mut ctx, options, points))
+                    }
+                };
+                match result {
+                    Ok(changes) if changes.crossings() < old => {
+                        routed[i] = self.apply_net(routed[i].index, &changes);
+                        rerouted.push(routed[i].index);
+                        improved = true;
+                    }
+                    _ => {
+                        self.apply(&restore);
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+
+        CrossingReport {
+            failed,
+            before,
+            after: self.crossings(),
+            rerouted,
+        }
+    }
+
+    /// Apply the route of net `index`, remembering the wires it adds
+    pub(crate) fn apply_net(&mut self, index: usize, changes: &ChangeSet) -> Routed {
+        let old: Vec<(usize, usize, [bool; 4])> = changes
+            .final_states()
+            .keys()
+            .map(|(x, y)| (*x, *y, self.directions(*x, *y)))
+            .collect();
+        self.apply(changes);
+        let wires = old
+            .into_iter()
+            .map(|(x, y, old)| {
+                let new = self.directions(x, y);
+                let mut added = [false; 4];
+                for i in 0..4 {
+                    added[i] = new[i] && !old[i];
+                }
+                (x, y, added)
+            })
+            .collect();
+        Routed { index, wires }
+    }
+
+    /// Crossings on the wires of `net`
+    fn net_crossings(&self, net: &Routed) -> usize {
+        net.wires
+            .iter()
+            .filter(|(x, y, _)| self.map[(*x, *y)] == CellState::Cross)
+            .count()
+    }
+
+    /// Changes removing the wires of `net`, leaving the wires it crosses
+    pub(crate) fn rip_up(&self, net: &Routed) -> ChangeSet {
+        let mut changes = ChangeSet::default();
+        for (x, y, added) in &net.wires {
+            let mut left = self.directions(*x, *y);
+            for i in 0..4 {
+                left[i] &= !added[i];
+            }
+            match self.terminal(*x, *y) {
+                // another net ends here as well
+                Some(mut terminal) if left.iter().any(|side| *side) => {
+                    terminal.set_directions(left);
+                    changes.changes.push((*x, *y, CellState::Terminal));
+                    changes.terminals.push((*x, *y, terminal));
+                }
+                Some(_) => changes.changes.push((*x, *y, CellState::Empty)),
+                None => {
+                    let state = CellState::from_directions(left).unwrap_or(CellState::Empty);
+                    changes.changes.push((*x, *y, state));
+                }
+            }
+        }
+        changes
+    }
+}
+
+impl ChangeSet {
+    /// Number of cells this changeset turns into crossings
+    pub(crate) fn crossings(&self) -> usize {
+        self.final_states()
+            .values()
+            .filter(|state| **state == CellState::Cross)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn minimize_crossings() {
+        // the second net goes straight across the first one at first
+        let mut maze = Maze::new(5, 5);
+        let nets = [Net::TwoPin(1, 2, 3, 2), Net::TwoPin(2, 0, 2, 4)];
+        let report = maze.route_minimizing_crossings_mut(&RouteOptions::default(), &nets, 4);
+        println!("{}", maze);
+        assert_eq!(report.before, 1);
+        assert_eq!(report.after, 0);
+        assert_eq!(report.rerouted, vec![1]);
+        assert_eq!(maze.crossings(), 0);
+        assert!(maze.verify());
+        assert_eq!(maze.get(2, 2), CellState::LR);
+
+        // a multi-pin net is rerouted around the wire as well
+        let mut maze = Maze::new(5, 5);
+        let nets = [
+            Net::TwoPin(1, 2, 3, 2),
+            Net::MultiPin(Points::new(&[(2, 0), (2, 4), (0, 4)])),
+        ];
+        let report = maze.route_minimizing_crossings_mut(&RouteOptions::default(), &nets, 4);
+        println!("{}", maze);
+        assert_eq!(report.before, 1);
+        assert_eq!(report.after, 0);
+        assert_eq!(report.rerouted, vec![1]);
+        assert!(maze.verify());
+
+        // the rerouted net ends on a terminal it shares with a third net
+        let mut maze = Maze::new(5, 5);
+        let nets = [
+            Net::TwoPin(1, 2, 3, 2),
+            Net::TwoPin(2, 0, 2, 4),
+            Net::TwoPin(2, 4, 4, 4),
+        ];
+        let report = maze.route_minimizing_crossings_mut(&RouteOptions::default(), &nets, 4);
+        println!("{}", maze);
+        assert_eq!(report.before, 1);
+        assert_eq!(report.after, 0);
+        assert_eq!(report.rerouted, vec![1]);
+        assert!(maze.verify());
+        let terminal = maze.terminal(2, 4).unwrap();
+        assert!(terminal.connects(Direction::R));
+        assert_eq!(
+            terminal.directions().iter().filter(|side| **side).count(),
+            2
+        );
+    }
+
+    quickcheck! {
+        fn qc_minimize_crossings(m: usize, n: usize, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 30 || n > 30 {
+                return true
+            }
+
+            let nets: Vec<Net> = points
+                .into_iter()
+                .enumerate()
+                .map(|(i, (x1, y1, x2, y2))| {
+                    let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+                    if i % 3 == 2 {
+                        Net::MultiPin(Points::new(&[(x1, y1), (x2, y2), (x1, y2)]))
+                    } else {
+                        Net::TwoPin(x1, y1, x2, y2)
+                    }
+                })
+                .collect();
+            let mut maze = Maze::new(m, n);
+            let report = maze.route_minimizing_crossings_mut(&RouteOptions::default(), &nets, 3);
+
+            // the first pass routes like plain sequential routing
+            let mut sequential = Maze::new(m, n);
+            let mut ctx = RouterContext::new(&sequential);
+            for net in &nets {
+                if let Ok(changes) = sequential.route_with(&mut ctx, &RouteOptions::default(), net) {
+                    sequential.apply(&changes);
+                }
+            }
+            report.after <= report.before
+                && report.before == sequential.crossings()
+                && report.after == maze.crossings()
+                && maze.verify()
+        }
+    }
+}
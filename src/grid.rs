@@ -0,0 +1,66 @@
+//! Flat row-major storage for per-cell data
+use crate::Direction;
+use std::ops::{Index, IndexMut};
+
+/// A m x n grid stored in a single buffer, one row (fixed `y`) after another,
+/// so horizontal neighbours are adjacent in memory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Grid<T> {
+    data: Vec<T>,
+    m: usize,
+    n: usize,
+}
+
+impl<T: Copy> Grid<T> {
+    pub fn new(m: usize, n: usize, value: T) -> Grid<T> {
+        Grid {
+            data: vec![value; m * n],
+            m,
+            n,
+        }
+    }
+
+    /// Set every cell to `value`
+    pub fn fill(&mut self, value: T) {
+        for item in &mut self.data {
+            *item = value;
+        }
+    }
+}
+
+impl<T> Grid<T> {
+    /// Neighbour of (x, y) in `direction`, if it is inside the grid
+    #[inline]
+    pub fn neighbor(&self, x: usize, y: usize, direction: Direction) -> Option<(usize, usize)> {
+        use Direction::*;
+        match direction {
+            L if x > 0 => Some((x - 1, y)),
+            R if x + 1 < self.m => Some((x + 1, y)),
+            D if y > 0 => Some((x, y - 1)),
+            U if y + 1 < self.n => Some((x, y + 1)),
+            _ => None,
+        }
+    }
+
+    pub fn index_of(&self, x: usize, y: usize) -> usize {
+        debug_assert!(x < self.m && y < self.n);
+        y * self.m + x
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        &self.data[self.index_of(x, y)]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    #[inline]
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+        let index = self.index_of(x, y);
+        &mut self.data[index]
+    }
+}
@@ -1,21 +1,29 @@
 //! Hadlock, Frank O.. “A shortest path algorithm for grid graphs.” Networks 7 (1977): 323-334.
 use super::*;
-
-fn manhattan_dist(x1: usize, y1: usize, x2: usize, y2: usize) -> usize {
-    max(x1, x2) - min(x1, x2) + max(y1, y2) - min(y1, y2)
+use crate::options::Rect;
+use std::collections::BTreeSet;
+
+/// Manhattan distance from (x, y) to the nearest cell of `rect`
+fn manhattan_dist(x: usize, y: usize, rect: &Rect) -> usize {
+    rect.x1.saturating_sub(x)
+        + x.saturating_sub(rect.x2)
+        + rect.y1.saturating_sub(y)
+        + y.saturating_sub(rect.y2)
 }
 
-#[derive(Eq)]
-struct HadlockCrossingState {
+#[derive(Eq, PartialEq)]
+pub(crate) struct HadlockCrossingState {
     x: usize,
     y: usize,
     dist: usize,
+    /// tie-breaking key, see `TieBreak`
+    key: u64,
 }
 
 impl Ord for HadlockCrossingState {
     fn cmp(&self, other: &Self) -> Ordering {
         // from small to big
-        other.dist.cmp(&self.dist)
+        (other.dist, other.key, other.x, other.y).cmp(&(self.dist, self.key, self.x, self.y))
     }
 }
 
@@ -25,94 +33,92 @@ impl PartialOrd for HadlockCrossingState {
     }
 }
 
-impl PartialEq for HadlockCrossingState {
-    fn eq(&self, other: &Self) -> bool {
-        self.dist == other.dist
-    }
-}
-
-#[wasm_bindgen]
 impl Maze {
-    /// Hadlock's algorithm, find shortest path like a*
-    pub fn hadlock(&self, x1: usize, y1: usize, x2: usize, y2: usize) -> Option<ChangeSet> {
-        use Direction::*;
-        let mut changes = vec![];
-        if self.map[x1][y1] != CellState::Empty || self.map[x2][y2] != CellState::Empty {
-            return None;
-        }
+    /// Hadlock's algorithm, find shortest path like a*,
+    /// reusing the scratch buffers in `ctx` within the limits of `options`
+    pub fn hadlock_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+    ) -> Result<ChangeSet, RouteError> {
         if x1 == x2 && y1 == y2 {
-            changes.push((x1, y1, CellState::Blocked));
-            return Some(ChangeSet { changes });
+            return Ok(self.single_terminal(x1, y1));
         }
+        let targets = [(x2, y2)].iter().copied().collect();
+        self.search_window(ctx, options, &[(x1, y1), (x2, y2)], |maze, ctx| {
+            maze.hadlock_search(ctx, &[(x1, y1)], &targets)
+        })
+    }
 
-        let mut queue = BinaryHeap::new();
-        let mut dir_map = vec![vec![None; self.n]; self.m];
-        dir_map[x1][y1] = Some(L);
-        queue.push(HadlockCrossingState {
-            x: x1,
-            y: y1,
-            dist: manhattan_dist(x1, y1, x2, y2),
-        });
-        let m = self.m as isize;
-        let n = self.n as isize;
-        while let Some(HadlockCrossingState { x, y, dist: _dist }) = queue.pop() {
-            if x == x2 && y == y2 {
-                // found
-                let mut direction = dir_map[x][y].unwrap();
-                let mut cur_x = x;
-                let mut cur_y = y;
-                loop {
-                    let (dx, dy) = direction.offset();
-                    let new_x = (cur_x as isize + dx) as usize;
-                    let new_y = (cur_y as isize + dy) as usize;
-                    if new_x == x1 && new_y == y1 {
-                        break;
-                    }
-                    let new_direction = dir_map[new_x][new_y].unwrap();
-                    changes.push((
-                        new_x,
-                        new_y,
-                        new_direction.get_new_cell_state(&direction, &self.map[new_x][new_y]),
-                    ));
-                    cur_x = new_x;
-                    cur_y = new_y;
-                    direction = new_direction;
-                }
+    /// Search from all `sources` to the nearest of `targets`, guided by the
+    /// distance to the bounding box of the targets
+    pub(crate) fn hadlock_search(
+        &self,
+        ctx: &mut RouterContext,
+        sources: &[(usize, usize)],
+        targets: &BTreeSet<(usize, usize)>,
+    ) -> Result<Option<ChangeSet>, RouteError> {
+        use Direction::*;
 
-                changes.push((x1, y1, CellState::Blocked));
-                changes.push((x2, y2, CellState::Blocked));
-                return Some(ChangeSet { changes });
+        let points: Vec<(usize, usize)> = targets.iter().copied().collect();
+        let target = Rect::bounding(&points);
+        ctx.begin();
+        for (x1, y1) in sources {
+            ctx.visit_source(*x1, *y1);
+            ctx.hadlock_heap.push(HadlockCrossingState {
+                x: *x1,
+                y: *y1,
+                dist: manhattan_dist(*x1, *y1, &target),
+                key: 0,
+            });
+        }
+        while let Some(HadlockCrossingState { x, y, .. }) = ctx.hadlock_heap.pop() {
+            ctx.expand()?;
+            if targets.contains(&(x, y)) {
+                // found
+                return Ok(Some(ctx.path(self, x, y)));
             }
 
-            let x = x as isize;
-            let y: isize = y as isize;
-            for direction in &[L, R, U, D] {
-                let (dx, dy) = direction.offset();
-                let new_x = x + dx;
-                let new_y = y + dy;
-                if 0 <= new_x && new_x < m && 0 <= new_y && new_y < n {
-                    let new_x = new_x as usize;
-                    let new_y = new_y as usize;
-                    if dir_map[new_x][new_y].is_none()
-                        && direction.can_cross(&self.map[new_x][new_y])
+            for direction in &ctx.order(x, y) {
+                if let Some((new_x, new_y)) = ctx.neighbor(x, y, *direction) {
+                    if !ctx.visited(new_x, new_y)
+                        && (direction.can_cross(&self.map[(new_x, new_y)], ctx.planar)
+                            || targets.contains(&(new_x, new_y)))
+                        && self.can_connect(x, y, new_x, new_y, *direction)
                     {
-                        dir_map[new_x as usize][new_y as usize] = Some(direction.opposite());
+                        ctx.visit(new_x, new_y, direction.opposite());
                         let towards = match direction {
-                            L => x2 < x as usize,
-                            R => x2 > x as usize,
-                            U => y2 > y as usize,
-                            D => y2 < y as usize,
+                            L => target.x2 < x,
+                            R => target.x1 > x,
+                            U => target.y1 > y,
+                            D => target.y2 < y,
                         };
-                        queue.push(HadlockCrossingState {
+                        let key = ctx.key(new_x, new_y);
+                        ctx.hadlock_heap.push(HadlockCrossingState {
                             x: new_x,
                             y: new_y,
-                            dist: manhattan_dist(new_x, new_y, x2, y2) + towards as usize,
+                            dist: manhattan_dist(new_x, new_y, &target) + towards as usize,
+                            key,
                         });
                     }
                 }
             }
         }
-        None
+        Ok(None)
+    }
+}
+
+#[wasm_bindgen]
+impl Maze {
+    /// Hadlock's algorithm, find shortest path like a*
+    pub fn hadlock(&self, x1: usize, y1: usize, x2: usize, y2: usize) -> Option<ChangeSet> {
+        let options = RouteOptions::default();
+        self.hadlock_with(&mut RouterContext::new(self), &options, x1, y1, x2, y2)
+            .ok()
     }
 
     pub fn hadlock_mut(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) -> bool {
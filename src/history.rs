@@ -0,0 +1,238 @@
+//! Undo and redo of maze edits
+use super::*;
+
+/// Edits grouped into one undo step
+struct Transaction {
+    label: String,
+    /// changes restoring the maze, applied from the last to the first
+    changes: Vec<ChangeSet>,
+}
+
+impl Transaction {
+    /// Apply the recorded changes to `maze`, returning the transaction
+    /// reverting them
+    fn revert(self, maze: &mut Maze) -> Transaction {
+        let changes = self
+            .changes
+            .iter()
+            .rev()
+            .map(|changes| maze.apply(changes))
+            .collect();
+        Transaction {
+            label: self.label,
+            changes,
+        }
+    }
+}
+
+/// Undo and redo stacks of edits to a maze.
+///
+/// Every edit made through the history is one undo step, unless edits are
+/// grouped into a labelled transaction with `begin` and `commit`. The
+/// history does not own the maze, so all edits and undos must be made on the
+/// same maze.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct MazeHistory {
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+    current: Option<Transaction>,
+}
+
+#[wasm_bindgen]
+impl MazeHistory {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> MazeHistory {
+        MazeHistory::default()
+    }
+
+    /// Start a transaction, edits until `commit` are undone in one step
+    pub fn begin(&mut self, label: &str) {
+        self.commit();
+        self.current = Some(Transaction {
+            label: label.to_string(),
+            changes: vec![],
+        });
+    }
+
+    /// Finish the transaction started by `begin`
+    pub fn commit(&mut self) {
+        if let Some(transaction) = self.current.take() {
+            if !transaction.changes.is_empty() {
+                self.undo.push(transaction);
+            }
+        }
+    }
+
+    /// Apply changeset to `maze`
+    pub fn apply(&mut self, maze: &mut Maze, changes: &ChangeSet, label: &str) {
+        let inverse = maze.apply(changes);
+        self.record(inverse, label);
+    }
+
+    /// Fill all points in rectangle (x1, y1) to (x2, y2) of `maze` to blocked
+    pub fn fill(&mut self, maze: &mut Maze, x1: usize, y1: usize, x2: usize, y2: usize) {
+        let changes = maze.rectangle(x1, y1, x2, y2, CellState::Blocked);
+        self.apply(maze, &changes, "fill");
+    }
+
+    /// Set all points in rectangle (x1, y1) to (x2, y2) of `maze` to empty
+    pub fn clean(&mut self, maze: &mut Maze, x1: usize, y1: usize, x2: usize, y2: usize) {
+        let changes = maze.rectangle(x1, y1, x2, y2, CellState::Empty);
+        self.apply(maze, &changes, "clean");
+    }
+
+    /// Set all cells of `maze` to empty
+    pub fn clear(&mut self, maze: &mut Maze) {
+        if maze.m == 0 || maze.n == 0 {
+            return;
+        }
+        let changes = maze.rectangle(0, 0, maze.m - 1, maze.n - 1, CellState::Empty);
+        self.apply(maze, &changes, "clear");
+    }
+
+    /// Revert the last step, including an unfinished transaction
+    pub fn undo(&mut self, maze: &mut Maze) -> bool {
+        self.commit();
+        match self.undo.pop() {
+            Some(transaction) => {
+                self.redo.push(transaction.revert(maze));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply the last undone step again
+    pub fn redo(&mut self, maze: &mut Maze) -> bool {
+        self.commit();
+        match self.redo.pop() {
+            Some(transaction) => {
+                self.undo.push(transaction.revert(maze));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.current.is_some() || !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Label of the step `undo` would revert
+    pub fn undo_label(&self) -> Option<String> {
+        self.current
+            .iter()
+            .chain(self.undo.last())
+            .next()
+            .map(|transaction| transaction.label.clone())
+    }
+
+    /// Label of the step `redo` would apply
+    pub fn redo_label(&self) -> Option<String> {
+        self.redo
+            .last()
+            .map(|transaction| transaction.label.clone())
+    }
+}
+
+impl MazeHistory {
+    fn record(&mut self, inverse: ChangeSet, label: &str) {
+        self.redo.clear();
+        match &mut self.current {
+            Some(transaction) => transaction.changes.push(inverse),
+            None => self.undo.push(Transaction {
+                label: label.to_string(),
+                changes: vec![inverse],
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn history() {
+        let mut maze = Maze::new(5, 5);
+        let mut history = MazeHistory::new();
+        assert!(!history.can_undo());
+
+        history.fill(&mut maze, 2, 0, 2, 2);
+        let filled = maze.to_string();
+        history.begin("route");
+        let changes = maze.lee(0, 0, 4, 0).unwrap();
+        history.apply(&mut maze, &changes, "lee");
+        let changes = maze.lee(0, 4, 4, 4).unwrap();
+        history.apply(&mut maze, &changes, "lee");
+        history.commit();
+        let routed = maze.to_string();
+        assert_eq!(history.undo_label(), Some("route".to_string()));
+
+        assert!(history.undo(&mut maze));
+        assert_eq!(maze.to_string(), filled);
+        assert_eq!(history.redo_label(), Some("route".to_string()));
+        assert!(history.undo(&mut maze));
+        assert_eq!(maze.to_string(), Maze::new(5, 5).to_string());
+        assert!(!history.undo(&mut maze));
+
+        assert!(history.redo(&mut maze));
+        assert!(history.redo(&mut maze));
+        assert_eq!(maze.to_string(), routed);
+        assert!(!history.redo(&mut maze));
+
+        // a new edit drops the undone steps
+        assert!(history.undo(&mut maze));
+        history.clear(&mut maze);
+        assert!(!history.can_redo());
+        assert!(history.undo(&mut maze));
+        assert_eq!(maze.to_string(), filled);
+
+        // nothing to clear
+        let mut empty = Maze::new(0, 0);
+        let mut history = MazeHistory::new();
+        history.clear(&mut empty);
+        assert!(!history.can_undo());
+    }
+
+    quickcheck! {
+        fn qc_history_undo_all(m: usize, n: usize, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            let mut maze = Maze::new(m, n);
+            let mut history = MazeHistory::new();
+            let mut states = vec![maze.to_string()];
+            for (i, (x1, y1, x2, y2)) in points.into_iter().enumerate() {
+                let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+                match i % 4 {
+                    0 => history.fill(&mut maze, x1, y1, x1, y1),
+                    1 => history.clean(&mut maze, x1, y1, x2, y2),
+                    _ => match maze.two_terminal(x1, y1, x2, y2) {
+                        Some(changes) => history.apply(&mut maze, &changes, "route"),
+                        None => continue,
+                    },
+                }
+                states.push(maze.to_string());
+            }
+            while history.undo(&mut maze) {
+                states.pop();
+                if states.last() != Some(&maze.to_string()) {
+                    return false;
+                }
+            }
+            states.len() == 1
+        }
+    }
+}
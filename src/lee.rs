@@ -2,322 +2,223 @@
 use super::*;
 use std::collections::BTreeSet;
 
+/// Search state of the Lee variants minimizing a cost before the distance
 #[derive(Eq, PartialEq)]
-struct LeeMinCrossingState {
+pub(crate) struct LeeCostState {
     x: usize,
     y: usize,
-    crosses: usize,
+    cost: usize,
     dist: usize,
+    /// tie-breaking key, see `TieBreak`
+    key: u64,
 }
 
-impl Ord for LeeMinCrossingState {
+impl Ord for LeeCostState {
     fn cmp(&self, other: &Self) -> Ordering {
         // from small to big
-        match other.crosses.cmp(&self.crosses) {
-            Ordering::Equal => match other.dist.cmp(&self.dist) {
-                Ordering::Equal => match self.x.cmp(&other.x) {
-                    Ordering::Equal => self.y.cmp(&other.y),
-                    res @ _ => res,
-                },
-                res @ _ => res,
-            },
-            res @ _ => res,
-        }
+        (other.cost, other.dist, other.key, other.x, other.y)
+            .cmp(&(self.cost, self.dist, self.key, self.x, self.y))
     }
 }
 
-impl PartialOrd for LeeMinCrossingState {
+impl PartialOrd for LeeCostState {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-#[derive(Eq, PartialEq)]
-struct LeeMinEdgeEffectState {
-    x: usize,
-    y: usize,
-    edges: usize,
-    dist: usize,
-}
-
-impl Ord for LeeMinEdgeEffectState {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // from small to big
-        match other.edges.cmp(&self.edges) {
-            Ordering::Equal => match other.dist.cmp(&self.dist) {
-                Ordering::Equal => match self.x.cmp(&other.x) {
-                    Ordering::Equal => self.y.cmp(&other.y),
-                    res @ _ => res,
-                },
-                res @ _ => res,
-            },
-            res @ _ => res,
-        }
-    }
-}
-
-impl PartialOrd for LeeMinEdgeEffectState {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-#[wasm_bindgen]
 impl Maze {
-    /// Lee's algorithm, find shortest path
-    pub fn lee(&self, x1: usize, y1: usize, x2: usize, y2: usize) -> Option<ChangeSet> {
-        use Direction::*;
-        let mut changes = vec![];
+    /// Lee's algorithm, find shortest path,
+    /// reusing the scratch buffers in `ctx` within the limits of `options`
+    pub fn lee_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+    ) -> Result<ChangeSet, RouteError> {
         if x1 == x2 && y1 == y2 {
-            changes.push((x1, y1, CellState::Blocked));
-            return Some(ChangeSet { changes: changes });
+            return Ok(self.single_terminal(x1, y1));
         }
+        let targets = [(x2, y2)].iter().copied().collect();
+        self.search_window(ctx, options, &[(x1, y1), (x2, y2)], |maze, ctx| {
+            maze.lee_search(ctx, &[(x1, y1)], &targets)
+        })
+    }
 
-        let mut queue = VecDeque::new();
-        let mut dir_map = vec![vec![None; self.n]; self.m];
-        dir_map[x1][y1] = Some(L);
-        queue.push_back((x1, y1));
-        let m = self.m as isize;
-        let n = self.n as isize;
-        while let Some((x, y)) = queue.pop_front() {
-            if x == x2 && y == y2 {
+    /// Breadth-first search from all `sources` to the nearest of `targets`
+    pub(crate) fn lee_search(
+        &self,
+        ctx: &mut RouterContext,
+        sources: &[(usize, usize)],
+        targets: &BTreeSet<(usize, usize)>,
+    ) -> Result<Option<ChangeSet>, RouteError> {
+        ctx.begin();
+        for (x1, y1) in sources {
+            ctx.visit_source(*x1, *y1);
+            ctx.queue.push_back((*x1 as u32, *y1 as u32));
+        }
+        while let Some((x, y)) = ctx.queue.pop_front() {
+            ctx.expand()?;
+            let (x, y) = (x as usize, y as usize);
+            if targets.contains(&(x, y)) {
                 // found
-                let mut direction = dir_map[x][y].unwrap();
-                let mut cur_x = x;
-                let mut cur_y = y;
-                while cur_x != x1 || cur_y != y1 {
-                    let (dx, dy) = direction.offset();
-                    let new_x = (cur_x as isize + dx) as usize;
-                    let new_y = (cur_y as isize + dy) as usize;
-                    if new_x == x1 && new_y == y1 {
-                        break;
-                    }
-
-                    let new_direction = dir_map[new_x][new_y].unwrap();
-                    changes.push((
-                        new_x,
-                        new_y,
-                        new_direction.get_new_cell_state(&direction, &self.map[new_x][new_y]),
-                    ));
-                    cur_x = new_x;
-                    cur_y = new_y;
-                    direction = new_direction;
-                }
-
-                changes.push((x1, y1, CellState::Blocked));
-                changes.push((x2, y2, CellState::Blocked));
-                return Some(ChangeSet { changes: changes });
+                return Ok(Some(ctx.path(self, x, y)));
             }
 
-            let x = x as isize;
-            let y: isize = y as isize;
-            for direction in &[L, R, U, D] {
-                let (dx, dy) = direction.offset();
-                let new_x = x + dx;
-                let new_y = y + dy;
-                if 0 <= new_x && new_x < m && 0 <= new_y && new_y < n {
-                    let new_x = new_x as usize;
-                    let new_y = new_y as usize;
-                    if dir_map[new_x][new_y].is_none()
-                        && direction.can_cross(&self.map[new_x][new_y])
+            for direction in &ctx.order(x, y) {
+                if let Some((new_x, new_y)) = ctx.neighbor(x, y, *direction) {
+                    if !ctx.visited(new_x, new_y)
+                        && (direction.can_cross(&self.map[(new_x, new_y)], ctx.planar)
+                            || targets.contains(&(new_x, new_y)))
+                        && self.can_connect(x, y, new_x, new_y, *direction)
                     {
-                        dir_map[new_x as usize][new_y as usize] = Some(direction.opposite());
-                        queue.push_back((new_x, new_y));
+                        ctx.visit(new_x, new_y, direction.opposite());
+                        ctx.queue.push_back((new_x as u32, new_y as u32));
                     }
                 }
             }
         }
-        None
-    }
-
-    /// Lee's algorithm, find shortest path
-    pub fn lee_mut(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) -> bool {
-        match self.lee(x1, y1, x2, y2) {
-            Some(changes) => {
-                self.apply(&changes);
-                true
-            }
-            None => false,
-        }
+        Ok(None)
     }
 
-    /// Lee's algorithm, find shortest path with minimum crossing
-    pub fn lee_minimum_crossing(
+    /// Lee's algorithm, find shortest path with minimum crossing,
+    /// reusing the scratch buffers in `ctx` within the limits of `options`
+    pub fn lee_minimum_crossing_with(
         &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
         x1: usize,
         y1: usize,
         x2: usize,
         y2: usize,
-    ) -> Option<ChangeSet> {
-        use Direction::*;
-        let mut changes = vec![];
+    ) -> Result<ChangeSet, RouteError> {
         if x1 == x2 && y1 == y2 {
-            changes.push((x1, y1, CellState::Blocked));
-            return Some(ChangeSet { changes: changes });
+            return Ok(self.single_terminal(x1, y1));
         }
+        self.search_window(ctx, options, &[(x1, y1), (x2, y2)], |maze, ctx| {
+            maze.lee_minimum_crossing_search(ctx, &options.weights, x1, y1, x2, y2)
+        })
+    }
 
-        let mut queue = BinaryHeap::new();
-        let mut dir_map = vec![vec![None; self.n]; self.m];
-        dir_map[x1][y1] = Some(L);
-        queue.push(LeeMinCrossingState {
+    fn lee_minimum_crossing_search(
+        &self,
+        ctx: &mut RouterContext,
+        weights: &Weights,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+    ) -> Result<Option<ChangeSet>, RouteError> {
+        ctx.begin();
+        ctx.visit_source(x1, y1);
+        ctx.cost_heap.push(LeeCostState {
             x: x1,
             y: y1,
-            crosses: 0,
+            cost: 0,
             dist: 0,
+            key: 0,
         });
-        let m = self.m as isize;
-        let n = self.n as isize;
-        while let Some(LeeMinCrossingState {
-            x,
-            y,
-            crosses,
-            dist,
-        }) = queue.pop()
+        while let Some(LeeCostState {
+            x, y, cost, dist, ..
+        }) = ctx.cost_heap.pop()
         {
+            ctx.expand()?;
             if x == x2 && y == y2 {
                 // found
-                let mut direction = dir_map[x][y].unwrap();
-                let mut cur_x = x;
-                let mut cur_y = y;
-                while cur_x != x1 || cur_y != y1 {
-                    let (dx, dy) = direction.offset();
-                    let new_x = (cur_x as isize + dx) as usize;
-                    let new_y = (cur_y as isize + dy) as usize;
-                    if new_x == x1 && new_y == y1 {
-                        break;
-                    }
-
-                    let new_direction = dir_map[new_x][new_y].unwrap();
-                    changes.push((
-                        new_x,
-                        new_y,
-                        new_direction.get_new_cell_state(&direction, &self.map[new_x][new_y]),
-                    ));
-                    cur_x = new_x;
-                    cur_y = new_y;
-                    direction = new_direction;
-                }
-
-                changes.push((x1, y1, CellState::Blocked));
-                changes.push((x2, y2, CellState::Blocked));
-                return Some(ChangeSet { changes });
+                return Ok(Some(ctx.path(self, x2, y2)));
             }
 
-            let x = x as isize;
-            let y: isize = y as isize;
-            for direction in &[L, R, U, D] {
-                let (dx, dy) = direction.offset();
-                let new_x = x + dx;
-                let new_y = y + dy;
-                if 0 <= new_x && new_x < m && 0 <= new_y && new_y < n {
-                    let new_x = new_x as usize;
-                    let new_y = new_y as usize;
-                    if dir_map[new_x][new_y].is_none()
-                        && direction.can_cross(&self.map[new_x][new_y])
+            for direction in &ctx.order(x, y) {
+                if let Some((new_x, new_y)) = ctx.neighbor(x, y, *direction) {
+                    if !ctx.visited(new_x, new_y)
+                        && (direction.can_cross(&self.map[(new_x, new_y)], ctx.planar)
+                            || (new_x, new_y) == (x2, y2))
+                        && self.can_connect(x, y, new_x, new_y, *direction)
                     {
-                        dir_map[new_x as usize][new_y as usize] = Some(direction.opposite());
-                        queue.push(LeeMinCrossingState {
+                        ctx.visit(new_x, new_y, direction.opposite());
+                        let key = ctx.key(new_x, new_y);
+                        ctx.cost_heap.push(LeeCostState {
                             x: new_x,
                             y: new_y,
-                            crosses: crosses
-                                + direction.will_cross(&self.map[new_x][new_y]) as usize,
+                            cost: cost
+                                + weights.length
+                                + weights.crossing
+                                    * direction.will_cross(&self.map[(new_x, new_y)]) as usize
+                                + weights.coupling * ctx.coupled(new_x, new_y),
                             dist: dist + 1,
+                            key,
                         });
                     }
                 }
             }
         }
-        None
+        Ok(None)
     }
 
-    /// Lee's algorithm, find shortest path with minimum crossing
-    pub fn lee_minimum_crossing_mut(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) -> bool {
-        match self.lee_minimum_crossing(x1, y1, x2, y2) {
-            Some(changes) => {
-                self.apply(&changes);
-                true
-            }
-            None => false,
+    /// Lee's algorithm, find shortest path with minimum edge effect,
+    /// reusing the scratch buffers in `ctx` within the limits of `options`
+    pub fn lee_minimum_edge_effect_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+    ) -> Result<ChangeSet, RouteError> {
+        if x1 == x2 && y1 == y2 {
+            return Ok(self.single_terminal(x1, y1));
         }
+        self.search_window(ctx, options, &[(x1, y1), (x2, y2)], |maze, ctx| {
+            maze.lee_minimum_edge_effect_search(ctx, &options.weights, x1, y1, x2, y2)
+        })
     }
 
-    /// Lee's algorithm, find shortest path with minimum edge effect
-    pub fn lee_minimum_edge_effect(
+    fn lee_minimum_edge_effect_search(
         &self,
+        ctx: &mut RouterContext,
+        weights: &Weights,
         x1: usize,
         y1: usize,
         x2: usize,
         y2: usize,
-    ) -> Option<ChangeSet> {
+    ) -> Result<Option<ChangeSet>, RouteError> {
         use Direction::*;
-        let mut changes = vec![];
-        if x1 == x2 && y1 == y2 {
-            changes.push((x1, y1, CellState::Blocked));
-            return Some(ChangeSet { changes: changes });
-        }
-
-        let mut queue = BinaryHeap::new();
-        let mut dir_map = vec![vec![None; self.n]; self.m];
-        dir_map[x1][y1] = Some(L);
-        queue.push(LeeMinEdgeEffectState {
+        ctx.begin();
+        ctx.visit_source(x1, y1);
+        ctx.cost_heap.push(LeeCostState {
             x: x1,
             y: y1,
-            edges: 0,
+            cost: 0,
             dist: 0,
+            key: 0,
         });
-        let m = self.m as isize;
-        let n = self.n as isize;
-        while let Some(LeeMinEdgeEffectState { x, y, edges, dist }) = queue.pop() {
+        while let Some(LeeCostState {
+            x, y, cost, dist, ..
+        }) = ctx.cost_heap.pop()
+        {
+            ctx.expand()?;
             if x == x2 && y == y2 {
                 // found
-                let mut direction = dir_map[x][y].unwrap();
-                let mut cur_x = x;
-                let mut cur_y = y;
-                while cur_x != x1 || cur_y != y1 {
-                    let (dx, dy) = direction.offset();
-                    let new_x = (cur_x as isize + dx) as usize;
-                    let new_y = (cur_y as isize + dy) as usize;
-                    if new_x == x1 && new_y == y1 {
-                        break;
-                    }
-
-                    let new_direction = dir_map[new_x][new_y].unwrap();
-                    changes.push((
-                        new_x,
-                        new_y,
-                        new_direction.get_new_cell_state(&direction, &self.map[new_x][new_y]),
-                    ));
-                    cur_x = new_x;
-                    cur_y = new_y;
-                    direction = new_direction;
-                }
-
-                changes.push((x1, y1, CellState::Blocked));
-                changes.push((x2, y2, CellState::Blocked));
-                return Some(ChangeSet { changes });
+                return Ok(Some(ctx.path(self, x2, y2)));
             }
 
-            let x = x as isize;
-            let y: isize = y as isize;
-            for direction in &[L, R, U, D] {
-                let (dx, dy) = direction.offset();
-                let new_x = x + dx;
-                let new_y = y + dy;
-                if 0 <= new_x && new_x < m && 0 <= new_y && new_y < n {
-                    let new_x = new_x as usize;
-                    let new_y = new_y as usize;
-                    if dir_map[new_x][new_y].is_none()
-                        && direction.can_cross(&self.map[new_x][new_y])
+            for direction in &ctx.order(x, y) {
+                if let Some((new_x, new_y)) = ctx.neighbor(x, y, *direction) {
+                    if !ctx.visited(new_x, new_y)
+                        && (direction.can_cross(&self.map[(new_x, new_y)], ctx.planar)
+                            || (new_x, new_y) == (x2, y2))
+                        && self.can_connect(x, y, new_x, new_y, *direction)
                     {
-                        dir_map[new_x][new_y] = Some(direction.opposite());
+                        ctx.visit(new_x, new_y, direction.opposite());
                         // edge effect: the number of neighbors that are not one of [Empty, LR, UD]
                         let mut new_edges = 0;
                         for direction in &[L, R, U, D] {
-                            let (dx, dy) = direction.offset();
-                            let new_x = new_x as isize + dx;
-                            let new_y = new_y as isize + dy;
-                            if 0 <= new_x && new_x < m && 0 <= new_y && new_y < n {
-                                let state = self.map[new_x as usize][new_y as usize];
+                            if let Some(neighbor) = self.map.neighbor(new_x, new_y, *direction) {
+                                let state = self.map[neighbor];
                                 if state != CellState::Empty
                                     && state != CellState::LR
                                     && state != CellState::UD
@@ -326,116 +227,200 @@ impl Maze {
                                 }
                             }
                         }
-                        queue.push(LeeMinEdgeEffectState {
+                        let key = ctx.key(new_x, new_y);
+                        ctx.cost_heap.push(LeeCostState {
                             x: new_x,
                             y: new_y,
-                            edges: edges + new_edges,
+                            cost: cost
+                                + weights.length
+                                + weights.edge * new_edges
+                                + weights.coupling * ctx.coupled(new_x, new_y),
                             dist: dist + 1,
+                            key,
                         });
                     }
                 }
             }
         }
-        None
+        Ok(None)
     }
 
-    /// Lee's algorithm, find shortest path with minimum edge effect
-    pub fn lee_minimum_edge_effect_mut(
-        &mut self,
-        x1: usize,
-        y1: usize,
-        x2: usize,
-        y2: usize,
-    ) -> bool {
-        match self.lee_minimum_edge_effect(x1, y1, x2, y2) {
-            Some(changes) => {
-                self.apply(&changes);
-                true
-            }
-            None => false,
-        }
-    }
-
-    /// Lee's algorithm, find rectified steiner tree
-    pub fn lee_multi(&self, points: &Points) -> Option<ChangeSet> {
-        use Direction::*;
-        let mut changes = vec![];
+    /// Lee's algorithm, find rectified steiner tree,
+    /// reusing the scratch buffers in `ctx` within the limits of `options`
+    pub fn lee_multi_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        points: &Points,
+    ) -> Result<ChangeSet, RouteError> {
         let points = points.get();
-        if points.len() == 0 {
-            return Some(ChangeSet { changes });
+        if points.is_empty() {
+            return Ok(ChangeSet::default());
         } else if points.len() == 1 {
-            changes.push((points[0].0, points[0].1, CellState::Blocked));
-            return Some(ChangeSet { changes });
+            return Ok(self.single_terminal(points[0].0, points[0].1));
         }
+        self.search_window(ctx, options, &points, |maze, ctx| {
+            maze.lee_multi_search(ctx, &points)
+        })
+    }
 
+    pub(crate) fn lee_multi_search(
+        &self,
+        ctx: &mut RouterContext,
+        points: &[(usize, usize)],
+    ) -> Result<Option<ChangeSet>, RouteError> {
+        let mut changes = vec![];
+        let mut terminals = Terminals::default();
         let mut dest_points = BTreeSet::new();
         for point in &points[1..] {
             dest_points.insert(point);
         }
 
-        let mut queue = VecDeque::new();
-        let mut dir_map = vec![vec![None; self.n]; self.m];
-        let mut new_map = self.map.clone();
+        ctx.begin();
         let (x1, y1) = points[0];
-        dir_map[x1][y1] = Some(L);
-        queue.push_back((x1, y1));
-        new_map[x1][y1] = CellState::Blocked;
-        changes.push((x1, y1, CellState::Blocked));
-
-        let m = self.m as isize;
-        let n = self.n as isize;
-        while let Some((x, y)) = queue.pop_front() {
+        ctx.visit_source(x1, y1);
+        ctx.queue.push_back((x1 as u32, y1 as u32));
+        ctx.write(x1, y1, CellState::Terminal);
+        terminals.add(self, &mut changes, x1, y1);
+
+        while let Some((x, y)) = ctx.queue.pop_front() {
+            ctx.expand()?;
+            let (x, y) = (x as usize, y as usize);
             if dest_points.contains(&(x, y)) {
                 // found
                 dest_points.remove(&(x, y));
-                new_map[x][y] = CellState::Blocked;
-                changes.push((x, y, CellState::Blocked));
+                ctx.write(x, y, CellState::Terminal);
+                terminals.add(self, &mut changes, x, y);
 
-                let mut direction = dir_map[x][y].unwrap();
+                let mut direction = ctx.direction(x, y);
+                terminals.connect(x, y, direction);
                 let mut cur_x = x;
                 let mut cur_y = y;
                 loop {
                     let (dx, dy) = direction.offset();
                     let new_x = (cur_x as isize + dx) as usize;
                     let new_y = (cur_y as isize + dy) as usize;
-                    if new_map[new_x][new_y] == CellState::Blocked {
+                    if ctx.cell(self, new_x, new_y) == CellState::Terminal {
+                        terminals.connect(new_x, new_y, direction.opposite());
                         break;
                     }
 
-                    let new_direction = dir_map[new_x][new_y].unwrap();
+                    let new_direction = ctx.direction(new_x, new_y);
                     let new_cell_state =
-                        new_direction.get_new_cell_state(&direction, &new_map[new_x][new_y]);
-                    new_map[new_x][new_y] = new_cell_state;
+                        new_direction.get_new_cell_state(&direction, &ctx.cell(self, new_x, new_y));
+                    if ctx.planar && new_cell_state == CellState::Cross {
+                        // a junction of four wires cannot be told apart from
+                        // a crossing
+                        return Ok(None);
+                    }
+                    ctx.write(new_x, new_y, new_cell_state);
                     changes.push((new_x, new_y, new_cell_state));
                     cur_x = new_x;
                     cur_y = new_y;
                     direction = new_direction;
                 }
 
-                if dest_points.len() == 0 {
-                    return Some(ChangeSet { changes: changes });
+                if dest_points.is_empty() {
+                    return Ok(Some(terminals.finish(changes)));
                 }
             }
 
-            let x = x as isize;
-            let y: isize = y as isize;
-            for direction in &[L, R, U, D] {
-                let (dx, dy) = direction.offset();
-                let new_x = x + dx;
-                let new_y = y + dy;
-                if 0 <= new_x && new_x < m && 0 <= new_y && new_y < n {
-                    let new_x = new_x as usize;
-                    let new_y = new_y as usize;
-                    if dir_map[new_x][new_y].is_none()
-                        && direction.can_cross(&new_map[new_x][new_y])
+            for direction in &ctx.order(x, y) {
+                if let Some((new_x, new_y)) = ctx.neighbor(x, y, *direction) {
+                    if !ctx.visited(new_x, new_y)
+                        && (direction.can_cross(&ctx.cell(self, new_x, new_y), ctx.planar)
+                            || dest_points.contains(&(new_x, new_y)))
+                        && self.can_connect(x, y, new_x, new_y, *direction)
                     {
-                        dir_map[new_x as usize][new_y as usize] = Some(direction.opposite());
-                        queue.push_back((new_x, new_y));
+                        ctx.visit(new_x, new_y, direction.opposite());
+                        ctx.queue.push_back((new_x as u32, new_y as u32));
                     }
                 }
             }
         }
-        None
+        Ok(None)
+    }
+}
+
+#[wasm_bindgen]
+impl Maze {
+    /// Lee's algorithm, find shortest path
+    pub fn lee(&self, x1: usize, y1: usize, x2: usize, y2: usize) -> Option<ChangeSet> {
+        let options = RouteOptions::default();
+        self.lee_with(&mut RouterContext::new(self), &options, x1, y1, x2, y2)
+            .ok()
+    }
+
+    /// Lee's algorithm, find shortest path
+    pub fn lee_mut(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) -> bool {
+        match self.lee(x1, y1, x2, y2) {
+            Some(changes) => {
+                self.apply(&changes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Lee's algorithm, find shortest path with minimum crossing
+    pub fn lee_minimum_crossing(
+        &self,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+    ) -> Option<ChangeSet> {
+        let options = RouteOptions::default();
+        self.lee_minimum_crossing_with(&mut RouterContext::new(self), &options, x1, y1, x2, y2)
+            .ok()
+    }
+
+    /// Lee's algorithm, find shortest path with minimum crossing
+    pub fn lee_minimum_crossing_mut(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) -> bool {
+        match self.lee_minimum_crossing(x1, y1, x2, y2) {
+            Some(changes) => {
+                self.apply(&changes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Lee's algorithm, find shortest path with minimum edge effect
+    pub fn lee_minimum_edge_effect(
+        &self,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+    ) -> Option<ChangeSet> {
+        let options = RouteOptions::default();
+        self.lee_minimum_edge_effect_with(&mut RouterContext::new(self), &options, x1, y1, x2, y2)
+            .ok()
+    }
+
+    /// Lee's algorithm, find shortest path with minimum edge effect
+    pub fn lee_minimum_edge_effect_mut(
+        &mut self,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+    ) -> bool {
+        match self.lee_minimum_edge_effect(x1, y1, x2, y2) {
+            Some(changes) => {
+                self.apply(&changes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Lee's algorithm, find rectified steiner tree
+    pub fn lee_multi(&self, points: &Points) -> Option<ChangeSet> {
+        let options = RouteOptions::default();
+        self.lee_multi_with(&mut RouterContext::new(self), &options, points)
+            .ok()
     }
 
     /// Lee's algorithm, find rectified steiner tree
@@ -0,0 +1,345 @@
+//! Routing two-pin nets to a length within a window
+use super::*;
+use crate::cleanup::direction;
+use crate::options::Rect;
+use std::collections::BTreeSet;
+
+/// Length window of a two-pin net, in steps between adjacent cells
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LengthConstraint {
+    pub min: usize,
+    pub max: usize,
+    /// Only add detours inside rectangle (x1, y1) to (x2, y2), anywhere if
+    /// `None`
+    pub region: Option<(usize, usize, usize, usize)>,
+}
+
+/// Outcome of `Maze::route_matched_mut`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchReport {
+    /// length of the longest net before matching
+    pub target: usize,
+    /// length of every net, `None` if it was not routed
+    pub lengths: Vec<Option<usize>>,
+    /// index and reason of every net that was not routed
+    pub unrouted: Vec<(usize, RouteError)>,
+    /// index of every routed net that could not be lengthened to within
+    /// the tolerance
+    pub unmatched: Vec<usize>,
+}
+
+impl Maze {
+    /// Lee's algorithm, then serpentine detours until the route between
+    /// (x1, y1) and (x2, y2) has a length within `constraint`, reusing the
+    /// scratch buffers in `ctx` within the limits of `options`. Routes
+    /// always have the parity of the distance between their terminals.
+    #[allow(clippy::too_many_arguments)]
+    pub fn lee_length_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+        constraint: &LengthConstraint,
+    ) -> Result<ChangeSet, RouteError> {
+        let changes = self.lee_with(ctx, options, x1, y1, x2, y2)?;
+        let path = match self.route_path(&changes) {
+            Some(path) => path,
+            // a single terminal
+            None if constraint.min == 0 => return Ok(changes),
+            None => return Err(RouteError::Unroutable),
+        };
+        let path = self
+            .serpentine(&changes, path, constraint)
+            .ok_or(RouteError::Unroutable)?;
+        Ok(self.path_changes(&changes, &path))
+    }
+
+    /// Route the two-pin `nets` with Lee's algorithm and apply the routes,
+    /// then lengthen every net to within `tolerance` of the longest one
+    /// with serpentine detours inside `region` (see `LengthConstraint`).
+    /// Nets never cross each other.
+    pub fn route_matched_mut(
+        &mut self,
+        options: &RouteOptions,
+        nets: &[(usize, usize, usize, usize)],
+        tolerance: usize,
+        region: Option<(usize, usize, usize, usize)>,
+    ) -> MatchReport {
+        let options = RouteOptions {
+            planar: true,
+            ..options.clone()
+        };
+        let mut ctx = RouterContext::new(self);
+        let mut routed = vec![];
+        let mut unrouted = vec![];
+        for (index, (x1, y1, x2, y2)) in nets.iter().enumerate() {
+            match self.lee_with(&mut ctx, &options, *x1, *y1, *x2, *y2) {
+                Ok(changes) => {
+                    let path = self
+                        .route_path(&changes)
+                        .unwrap_or_else(|| vec![(*x1, *y1)]);
+                    routed.push((
+                        index,
+                        changes.clone(),
+                        path,
+                        self.apply_net(index, &changes),
+                    ));
+                }
+                Err(err) => unrouted.push((index, err)),
+            }
+        }
+
+        let mut lengths = vec![None; nets.len()];
+        let target = routed
+            .iter()
+            .map(|(_, _, path, _)| path.len() - 1)
+            .max()
+            .unwrap_or(0);
+        let constraint = LengthConstraint {
+            min: target.saturating_sub(tolerance),
+            max: target,
+            region,
+        };
+        let mut unmatched = vec![];
+        for (index, changes, path, net) in routed {
+            lengths[index] = Some(path.len() - 1);
+            if path.len() > constraint.min {
+                continue;
+            }
+            let restore = self.apply(&self.rip_up(&net));
+            match self.serpentine(&changes, path, &constraint) {
+                Some(path) if path.len() > 1 => {
+                    lengths[index] = Some(path.len() - 1);
+                    let changes = self.path_changes(&changes, &path);
+                    self.apply_net(index, &changes);
+                }
+                _ => {
+                    self.apply(&restore);
+                    unmatched.push(index);
+                }
+            }
+        }
+        MatchReport {
+            target,
+            lengths,
+            unrouted,
+            unmatched,
+        }
+    }
+
+    /// Lengthen `path`, a route of `changes`, by detours to the side of its
+    /// steps until its length is within `constraint`
+    fn serpentine(
+        &self,
+        changes: &ChangeSet,
+        mut path: Vec<(usize, usize)>,
+        constraint: &LengthConstraint,
+    ) -> Option<Vec<(usize, usize)>> {
+        let region = match constraint.region {
+            Some((x1, y1, x2, y2)) => Rect {
+                x1: min(x1, x2),
+                y1: min(y1, y2),
+                x2: max(x1, x2),
+                y2: max(y1, y2),
+            },
+            None => Rect::full(self.m, self.n),
+        };
+        let pins: Vec<u32> = changes.terminals.iter().map(|(_, _, t)| t.pin).collect();
+        let keep_out = self.keep_out(&pins, &[path[0], path[path.len() - 1]]);
+        let free = |(x, y): (usize, usize), used: &BTreeSet<(usize, usize)>| {
+            x >= region.x1
+                && x <= region.x2
+                && y >= region.y1
+                && y <= region.y2
+                && self.map[(x, y)] == CellState::Empty
+                && !used.contains(&(x, y))
+                && keep_out
+                    .as_ref()
+                    .is_none_or(|(_, keep_out)| !keep_out[(x, y)])
+        };
+
+        while path.len() - 1 < constraint.min {
+            let length = path.len() - 1;
+            if length + 2 > constraint.max {
+                return None;
+            }
+            // each detour of height k adds 2k steps
+            let height = min(
+                (constraint.min - length).div_ceil(2),
+                (constraint.max - length) / 2,
+            );
+            let used: BTreeSet<(usize, usize)> = path.iter().copied().collect();
+            let mut detour = None;
+            'search: for i in 0..path.len() - 1 {
+                let (a, b) = (path[i], path[i + 1]);
+                let step = direction(a, b);
+                let sides = match step {
+                    Direction::L | Direction::R => [Direction::U, Direction::D],
+                    Direction::U | Direction::D => [Direction::L, Direction::R],
+                };
+                for side in &sides {
+                    // raise the step between a and b by k cells
+                    let mut up = vec![];
+                    let mut down = vec![];
+                    let (mut last_a, mut last_b) = (a, b);
+                    while up.len() < height {
+                        let next = match (
+                            self.map.neighbor(last_a.0, last_a.1, *side),
+                            self.map.neighbor(last_b.0, last_b.1, *side),
+                        ) {
+                            (Some(next_a), Some(next_b))
+                                if free(next_a, &used)
+                                    && free(next_b, &used)
+                                    && self.can_connect(
+                                        last_a.0, last_a.1, next_a.0, next_a.1, *side,
+                                    )
+                                    && self.can_connect(
+                                        next_b.0,
+                                        next_b.1,
+                                        last_b.0,
+                                        last_b.1,
+                                        side.opposite(),
+                                    ) =>
+                            {
+                                (next_a, next_b)
+                            }
+                            _ => break,
+                        };
+                        up.push(next.0);
+                        down.push(next.1);
+                        last_a = next.0;
+                        last_b = next.1;
+                    }
+                    // the top of the detour must connect as well
+                    while let (Some(top_a), Some(top_b)) = (up.last(), down.last()) {
+                        if self.can_connect(top_a.0, top_a.1, top_b.0, top_b.1, step) {
+                            break;
+                        }
+                        up.pop();
+                        down.pop();
+                    }
+                    if !up.is_empty() {
+                        down.reverse();
+                        up.extend(down);
+                        detour = Some((i, up));
+                        break 'search;
+                    }
+                }
+            }
+            let (i, cells) = detour?;
+            path.splice(i + 1..i + 1, cells);
+        }
+        if path.len() - 1 <= constraint.max {
+            Some(path)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn length() {
+        let maze = Maze::new(5, 5);
+        let mut ctx = RouterContext::new(&maze);
+        let options = RouteOptions::default();
+        let constraint = |min, max| LengthConstraint {
+            min,
+            max,
+            region: None,
+        };
+        let changes = maze
+            .lee_length_with(&mut ctx, &options, 0, 0, 4, 0, &constraint(10, 10))
+            .unwrap();
+        let mut routed = maze.clone();
+        routed.apply(&changes);
+        println!("{}", routed);
+        assert!(routed.verify());
+        assert_eq!(changes.len(), 11);
+
+        // odd lengths have the wrong parity
+        assert!(maze
+            .lee_length_with(&mut ctx, &options, 0, 0, 4, 0, &constraint(5, 5))
+            .is_err());
+        // shorter than the shortest route
+        assert!(maze
+            .lee_length_with(&mut ctx, &options, 0, 0, 4, 0, &constraint(0, 3))
+            .is_err());
+        // no room in the bottom row
+        let row = LengthConstraint {
+            region: Some((0, 0, 4, 0)),
+            ..constraint(6, 6)
+        };
+        assert!(maze
+            .lee_length_with(&mut ctx, &options, 0, 0, 4, 0, &row)
+            .is_err());
+    }
+
+    #[test]
+    fn match_lengths() {
+        let mut maze = Maze::new(8, 8);
+        let nets = [(0, 0, 7, 0), (0, 3, 3, 3), (0, 6, 5, 6)];
+        let report = maze.route_matched_mut(&RouteOptions::default(), &nets, 0, None);
+        println!("{}", maze);
+        assert!(maze.verify());
+        assert_eq!(report.target, 7);
+        assert_eq!(report.lengths, vec![Some(7), Some(7), Some(7)]);
+        assert!(report.unmatched.is_empty());
+
+        // the short net has no room to grow
+        let mut maze = Maze::new(8, 2);
+        let nets = [(0, 0, 7, 0), (0, 1, 1, 1)];
+        let report = maze.route_matched_mut(&RouteOptions::default(), &nets, 2, None);
+        assert_eq!(report.lengths, vec![Some(7), Some(1)]);
+        assert_eq!(report.unmatched, vec![1]);
+        assert!(maze.verify());
+    }
+
+    quickcheck! {
+        fn qc_length(m: usize, n: usize, points: (usize, usize, usize, usize), min: usize, extra: usize) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 30 || n > 30 {
+                return true
+            }
+
+            let maze = Maze::new(m, n);
+            let (x1, y1, x2, y2) = points;
+            let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+            let constraint = LengthConstraint {
+                min: min % 50,
+                max: min % 50 + extra % 5,
+                region: None,
+            };
+            let mut ctx = RouterContext::new(&maze);
+            // shortest length of the right parity within the window
+            let distance = x1.abs_diff(x2) + y1.abs_diff(y2);
+            let shortest = (max(constraint.min, distance)..=constraint.max)
+                .find(|length| (length - distance) % 2 == 0);
+            // a straight route has room for one detour next to it
+            let straight = distance > 0 && (x1 == x2 && m > 1 || y1 == y2 && n > 1);
+            let routable = match shortest {
+                Some(length) => length == distance || straight && length == distance + 2,
+                None => false,
+            };
+            match maze.lee_length_with(&mut ctx, &RouteOptions::default(), x1, y1, x2, y2, &constraint) {
+                Ok(changes) => {
+                    let length = changes.len() - 1;
+                    let mut routed = maze.clone();
+                    routed.apply(&changes);
+                    routed.verify() && length >= constraint.min && length <= constraint.max
+                }
+                Err(_) => !routable,
+            }
+        }
+    }
+}
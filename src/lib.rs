@@ -1,18 +1,58 @@
+pub use batch::{Net, TransactionError, TransactionReport};
+pub use bus::BusReport;
+pub use chain::{ChainError, ChainReport, Objective, Router, RouterChain};
+pub use class::{Algorithm, NetClass, Netlist, NetlistError};
+pub use context::RouterContext;
+pub use coupling::ParallelRun;
+pub use crossing::CrossingReport;
+use grid::Grid;
+pub use history::MazeHistory;
+pub use length::{LengthConstraint, MatchReport};
+pub use options::{RouteError, RouteOptions, TieBreak, Weights};
+pub use orientation::Orientation;
+pub use pair::PairReport;
+pub use pin::Pin;
+use rule::NetCells;
+pub use rule::{Rule, Violation};
 use serde_derive::{Deserialize, Serialize};
-use serde_wasm_bindgen;
 use std::cmp::{max, min, Ordering};
-use std::collections::{BinaryHeap, VecDeque};
+use std::collections::{BTreeMap, BinaryHeap, VecDeque};
 use std::fmt;
+use std::sync::OnceLock;
+pub use terminal::Terminal;
+use terminal::Terminals;
+pub use timing::{Parasitics, TimingReport};
 use wasm_bindgen::prelude::*;
 
+mod batch;
+mod bus;
+mod chain;
+mod changeset;
+mod class;
+mod cleanup;
+mod context;
+mod coupling;
+mod crossing;
+mod grid;
 mod hadlock;
+mod history;
 mod lee;
+mod length;
 mod mikami_tabuchi;
+mod options;
+mod orientation;
+mod pair;
+mod pin;
+mod rule;
 mod soukup;
 mod stst;
+mod terminal;
+mod timing;
+mod wall;
 
-/// Cell's state
+/// Cell's state, stored in one byte per cell
 #[wasm_bindgen]
+#[repr(u8)]
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum CellState {
     /// Empty cell
@@ -44,6 +84,8 @@ pub enum CellState {
     RDL,
     /// Down, left and up
     DLU,
+    /// Terminal of a net, see `Maze::terminal` for its connections
+    Terminal,
 }
 
 impl fmt::Display for CellState {
@@ -63,6 +105,7 @@ impl fmt::Display for CellState {
             URD => '┣',
             RDL => '┳',
             DLU => '┫',
+            Terminal => 'o',
         };
         write!(f, "{}", ch)
     }
@@ -73,8 +116,7 @@ impl CellState {
     fn extract(&self) -> [bool; 4] {
         use CellState::*;
         match self {
-            Empty => [false, false, false, false],
-            Blocked => [true, true, true, true],
+            Empty | Blocked | Terminal => [false, false, false, false],
             Cross => [true, true, true, true],
             LR => [true, true, false, false],
             UD => [false, false, true, true],
@@ -88,10 +130,33 @@ impl CellState {
             DLU => [true, false, true, true],
         }
     }
+
+    /// Wire connecting the given directions, the inverse of `extract`
+    /// for wires. A single direction is not a valid wire.
+    fn from_directions(directions: [bool; 4]) -> Option<CellState> {
+        use CellState::*;
+        // L, R, U, D
+        match directions {
+            [false, false, false, false] => Some(Empty),
+            [false, false, true, true] => Some(UD),
+            [false, true, false, true] => Some(RD),
+            [false, true, true, false] => Some(RU),
+            [true, false, false, true] => Some(LD),
+            [true, false, true, false] => Some(LU),
+            [true, true, false, false] => Some(LR),
+            [false, true, true, true] => Some(URD),
+            [true, false, true, true] => Some(DLU),
+            [true, true, false, true] => Some(RDL),
+            [true, true, true, false] => Some(LUR),
+            [true, true, true, true] => Some(Cross),
+            _ => None,
+        }
+    }
 }
 
-/// Four directions
-#[derive(Copy, Clone, Debug)]
+/// Four directions, `Option<Direction>` fits in one byte
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Direction {
     L,
     R,
@@ -111,8 +176,27 @@ impl Direction {
         }
     }
 
-    fn can_cross(&self, state: &CellState) -> bool {
+    fn from_index(index: usize) -> Direction {
+        use Direction::*;
+        [L, R, U, D][index]
+    }
+
+    /// The two directions turning 90 degrees from this one
+    fn perpendicular(&self) -> &'static [Direction; 2] {
         use Direction::*;
+        match self {
+            L | R => &[U, D],
+            U | D => &[L, R],
+        }
+    }
+
+    /// Whether a wire going this way may enter a cell in `state`, crossing
+    /// a perpendicular wire unless `planar`
+    fn can_cross(&self, state: &CellState, planar: bool) -> bool {
+        use Direction::*;
+        if planar {
+            return *state == CellState::Empty;
+        }
         match self {
             L | R => *state == CellState::Empty || *state == CellState::UD,
             U | D => *state == CellState::Empty || *state == CellState::LR,
@@ -149,28 +233,14 @@ impl Direction {
 
     fn get_new_cell_state(&self, prev: &Direction, old_state: &CellState) -> CellState {
         use CellState::*;
-        if *old_state == Blocked {
-            return Blocked;
+        if *old_state == Blocked || *old_state == Terminal {
+            return *old_state;
         }
         // L, R, U, D
         let mut directions = old_state.extract();
         directions[self.index()] = true;
         directions[prev.opposite().index()] = true;
-        // L, R, U, D
-        match directions {
-            [false, false, true, true] => UD,
-            [false, true, false, true] => RD,
-            [false, true, true, false] => RU,
-            [true, false, false, true] => LD,
-            [true, false, true, false] => LU,
-            [true, true, false, false] => LR,
-            [false, true, true, true] => URD,
-            [true, false, true, true] => DLU,
-            [true, true, false, true] => RDL,
-            [true, true, true, false] => LUR,
-            [true, true, true, true] => Cross,
-            _ => unreachable!(),
-        }
+        CellState::from_directions(directions).unwrap()
     }
 }
 
@@ -178,7 +248,25 @@ impl Direction {
 #[wasm_bindgen]
 #[derive(Clone)]
 pub struct Maze {
-    map: Vec<Vec<CellState>>,
+    map: Grid<CellState>,
+    /// connections of the cells in `map` that are `CellState::Terminal`
+    terminals: BTreeMap<(usize, usize), Terminal>,
+    /// sides (L, R, U, D) wires may use at a cell, all if absent
+    access: BTreeMap<(usize, usize), [bool; 4]>,
+    /// blocked edges to the right and upper neighbours, allocated when the
+    /// first edge is blocked
+    walls: Option<Grid<u8>>,
+    /// directions wires may run in, allocated when the first region is
+    /// restricted
+    orientations: Option<Grid<Orientation>>,
+    /// never cross wires, whatever the options of a router say
+    planar: bool,
+    /// width and spacing of the nets with terminals of a pin, the default
+    /// rule if absent
+    rules: BTreeMap<u32, Rule>,
+    /// wires and terminals grouped into nets, found on first use after a
+    /// change
+    nets: OnceLock<Vec<NetCells>>,
     m: usize,
     n: usize,
 }
@@ -189,14 +277,31 @@ impl Maze {
     #[wasm_bindgen(constructor)]
     pub fn new(m: usize, n: usize) -> Maze {
         Maze {
-            map: vec![vec![CellState::Empty; n]; m],
+            map: Grid::new(m, n, CellState::Empty),
+            terminals: BTreeMap::new(),
+            access: BTreeMap::new(),
+            walls: None,
+            orientations: None,
+            planar: false,
+            rules: BTreeMap::new(),
+            nets: OnceLock::new(),
             m,
             n,
         }
     }
 
     pub fn get(&self, x: usize, y: usize) -> CellState {
-        self.map[x][y]
+        self.map[(x, y)]
+    }
+
+    /// Forbid all routers to cross wires in this maze, for single layer
+    /// processes
+    pub fn set_planar(&mut self, planar: bool) {
+        self.planar = planar;
+    }
+
+    pub fn planar(&self) -> bool {
+        self.planar
     }
 
     /// fills all points in rectangle (x1, y1) to (x2, y2) to blocked
@@ -207,9 +312,11 @@ impl Maze {
         let to_y = max(y1, y2);
         assert!(to_x < self.m);
         assert!(to_y < self.n);
+        self.nets = OnceLock::new();
         for i in from_x..(to_x + 1) {
             for j in from_y..(to_y + 1) {
-                self.map[i][j] = CellState::Blocked;
+                self.map[(i, j)] = CellState::Blocked;
+                self.terminals.remove(&(i, j));
             }
         }
     }
@@ -229,9 +336,11 @@ impl Maze {
         let to_y = max(y1, y2);
         assert!(to_x < self.m);
         assert!(to_y < self.n);
+        self.nets = OnceLock::new();
         for i in from_x..(to_x + 1) {
             for j in from_y..(to_y + 1) {
-                self.map[i][j] = CellState::Empty;
+                self.map[(i, j)] = CellState::Empty;
+                self.terminals.remove(&(i, j));
             }
         }
     }
@@ -245,43 +354,68 @@ impl Maze {
 
     /// Set all cells to empty
     pub fn clear_mut(&mut self) {
-        for line in &mut self.map {
-            for item in line {
-                *item = CellState::Empty;
+        self.map.fill(CellState::Empty);
+        self.terminals.clear();
+        self.nets = OnceLock::new();
+    }
+
+    /// Apply changeset, returning the changeset that reverts it
+    pub fn apply(&mut self, changes: &ChangeSet) -> ChangeSet {
+        self.nets = OnceLock::new();
+        let mut terminals = BTreeMap::new();
+        let cells = changes.changes.iter().map(|(x, y, _)| (*x, *y));
+        for (x, y) in cells.chain(changes.terminals.iter().map(|(x, y, _)| (*x, *y))) {
+            if let Some(terminal) = self.terminal(x, y) {
+                terminals.entry((x, y)).or_insert(terminal);
             }
         }
-    }
 
-    /// Apply changeset
-    pub fn apply(&mut self, changes: &ChangeSet) {
+        let mut inverse = Vec::with_capacity(changes.changes.len());
         for (x, y, state) in &changes.changes {
-            self.map[*x][*y] = *state;
+            inverse.push((*x, *y, self.map[(*x, *y)]));
+            self.map[(*x, *y)] = *state;
+            if *state != CellState::Terminal {
+                self.terminals.remove(&(*x, *y));
+            }
+        }
+        for (x, y, terminal) in &changes.terminals {
+            if self.map[(*x, *y)] == CellState::Terminal {
+                self.terminals.insert((*x, *y), *terminal);
+            }
+        }
+        // restore the oldest state of cells changed more than once
+        inverse.reverse();
+        ChangeSet {
+            changes: inverse,
+            terminals: terminals
+                .into_iter()
+                .map(|((x, y), terminal)| (x, y, terminal))
+                .collect(),
         }
     }
 
-    /// Sanity check
+    /// Sanity check, every wire and terminal must connect to a neighbour
+    /// connecting back
     pub fn verify(&self) -> bool {
         use Direction::*;
         for x in 0..self.m {
             for y in 0..self.n {
-                if self.map[x][y] == CellState::Blocked || self.map[x][y] == CellState::Empty {
-                    continue;
-                }
                 // L, R, U, D
-                let ways = self.map[x][y].extract();
+                let ways = self.directions(x, y);
                 let dirs = [L, R, U, D];
                 for i in 0..4 {
                     if ways[i] {
-                        let (dx, dy) = dirs[i].offset();
-                        let nx = x as isize + dx;
-                        let ny = y as isize + dy;
-                        if nx < 0 || nx >= self.m as isize || ny < 0 || ny >= self.n as isize {
+                        if !self.allows(x, y, dirs[i]) || self.walled(x, y, dirs[i]) {
                             return false;
                         }
-                        let cell = self.map[nx as usize][ny as usize];
-                        let ways_other = cell.extract();
-                        if !ways_other[dirs[i].opposite().index()] {
-                            return false;
+                        match self.map.neighbor(x, y, dirs[i]) {
+                            Some((nx, ny)) => {
+                                let ways_other = self.directions(nx, ny);
+                                if !ways_other[dirs[i].opposite().index()] {
+                                    return false;
+                                }
+                            }
+                            None => return false,
                         }
                     }
                 }
@@ -290,15 +424,16 @@ impl Maze {
         true
     }
 
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         format!("{}", self)
     }
 
     pub fn two_terminal(&self, x1: usize, y1: usize, x2: usize, y2: usize) -> Option<ChangeSet> {
-        self.mikami_tabuchi(x1, y1, x2, y2).or_else(|| {
-            self.hadlock(x1, y1, x2, y2)
-                .or_else(|| self.lee(x1, y1, x2, y2))
-        })
+        let options = RouteOptions::default();
+        self.two_terminal_with(&mut RouterContext::new(self), &options, x1, y1, x2, y2)
+            .ok()
+            .map(|report| report.changes)
     }
 
     pub fn two_terminal_mut(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) -> bool {
@@ -312,10 +447,10 @@ impl Maze {
     }
 
     pub fn multi_terminal(&self, points: &Points) -> Option<ChangeSet> {
-        self.stst(points).or_else(|| {
-            self.mikami_tabuchi_multi(points)
-                .or_else(|| self.lee_multi(points))
-        })
+        let options = RouteOptions::default();
+        self.multi_terminal_with(&mut RouterContext::new(self), &options, points)
+            .ok()
+            .map(|report| report.changes)
     }
 
     pub fn multi_terminal_mut(&mut self, points: &Points) -> bool {
@@ -329,13 +464,79 @@ impl Maze {
     }
 }
 
+impl Maze {
+    /// Changes setting all points in rectangle (x1, y1) to (x2, y2) to `state`
+    pub(crate) fn rectangle(
+        &self,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+        state: CellState,
+    ) -> ChangeSet {
+        let from_x = min(x1, x2);
+        let to_x = max(x1, x2);
+        let from_y = min(y1, y2);
+        let to_y = max(y1, y2);
+        assert!(to_x < self.m);
+        assert!(to_y < self.n);
+        let mut changes = vec![];
+        for i in from_x..(to_x + 1) {
+            for j in from_y..(to_y + 1) {
+                changes.push((i, j, state));
+            }
+        }
+        ChangeSet {
+            changes,
+            terminals: vec![],
+        }
+    }
+
+    /// Route a two-pin net with Mikami-Tabuchi, Hadlock, then Lee, reusing
+    /// the scratch buffers in `ctx`, see `RouterChain::two_pin`. Each router
+    /// gets the full budget of `options`, the report names the one that
+    /// found the route.
+    pub fn two_terminal_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+    ) -> Result<ChainReport, ChainError> {
+        let net = Net::TwoPin(x1, y1, x2, y2);
+        RouterChain::two_pin().route(self, ctx, options, &net)
+    }
+
+    /// Route a multi-pin net with a single trunk Steiner tree,
+    /// Mikami-Tabuchi, then Lee, reusing the scratch buffers in `ctx`, see
+    /// `RouterChain::multi_pin`. Each router gets the full budget of
+    /// `options`, the report names the one that found the route.
+    pub fn multi_terminal_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        points: &Points,
+    ) -> Result<ChainReport, ChainError> {
+        let net = Net::MultiPin(points.clone());
+        RouterChain::multi_pin().route(self, ctx, options, &net)
+    }
+}
+
 impl fmt::Display for Maze {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.has_walls() {
+            return self.fmt_walls(f);
+        }
         for y in (0..self.n).rev() {
             for x in 0..self.m {
-                write!(f, "{}", self.map[x][y])?;
+                match self.terminal(x, y) {
+                    Some(terminal) => write!(f, "{}", terminal)?,
+                    None => write!(f, "{}", self.map[(x, y)])?,
+                }
             }
-            writeln!(f, "")?;
+            writeln!(f)?;
         }
         Ok(())
     }
@@ -343,9 +544,24 @@ impl fmt::Display for Maze {
 
 /// Represent a changeset of maze cells.
 #[wasm_bindgen]
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChangeSet {
     changes: Vec<(usize, usize, CellState)>,
+    /// connections of the cells changed to `CellState::Terminal`
+    #[serde(default)]
+    terminals: Vec<(usize, usize, Terminal)>,
+}
+
+impl ChangeSet {
+    /// Bounding box of the changed cells
+    pub(crate) fn bounding(&self) -> Option<options::Rect> {
+        let points: Vec<(usize, usize)> = self.changes.iter().map(|(x, y, _)| (*x, *y)).collect();
+        if points.is_empty() {
+            None
+        } else {
+            Some(options::Rect::bounding(&points))
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -354,6 +570,13 @@ impl ChangeSet {
     pub fn to_js(&self) -> JsValue {
         serde_wasm_bindgen::to_value(&self.changes).unwrap()
     }
+
+    /// Assign all terminals in this changeset to `pin`
+    pub fn set_pin(&mut self, pin: u32) {
+        for (_, _, terminal) in &mut self.terminals {
+            terminal.pin = pin;
+        }
+    }
 }
 
 /// A vector of points.
@@ -399,7 +622,6 @@ impl Points {
 mod tests {
     use super::*;
     use quickcheck::*;
-    use serde_json;
 
     #[test]
     fn serde_points() {
@@ -412,6 +634,12 @@ mod tests {
         );
     }
 
+    #[test]
+    fn compact_cells() {
+        assert_eq!(std::mem::size_of::<CellState>(), 1);
+        assert_eq!(std::mem::size_of::<Option<Direction>>(), 1);
+    }
+
     quickcheck! {
         fn qc_sanity(m: usize, n: usize) -> bool {
             // check oom
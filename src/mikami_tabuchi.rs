@@ -2,189 +2,221 @@
 use super::*;
 use std::collections::BTreeSet;
 
-#[wasm_bindgen]
 impl Maze {
-    /// Mikami-Tabuchi's algorithm, line search
-    pub fn mikami_tabuchi(&self, x1: usize, y1: usize, x2: usize, y2: usize) -> Option<ChangeSet> {
-        use Direction::*;
-        let mut changes = vec![];
+    /// Mikami-Tabuchi's algorithm, line search,
+    /// reusing the scratch buffers in `ctx` within the limits of `options`
+    pub fn mikami_tabuchi_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+    ) -> Result<ChangeSet, RouteError> {
         if x1 == x2 && y1 == y2 {
-            changes.push((x1, y1, CellState::Blocked));
-            return Some(ChangeSet { changes: changes });
+            return Ok(self.single_terminal(x1, y1));
         }
+        self.search_window(ctx, options, &[(x1, y1), (x2, y2)], |maze, ctx| {
+            maze.mikami_tabuchi_search(ctx, x1, y1, x2, y2)
+        })
+    }
 
-        let mut queue = VecDeque::new();
-        let mut dir_map = vec![vec![None; self.n]; self.m];
-        dir_map[x1][y1] = Some(L);
-        queue.push_back((x1, y1, L));
-        queue.push_back((x1, y1, R));
-        queue.push_back((x1, y1, U));
-        queue.push_back((x1, y1, D));
-        let m = self.m as isize;
-        let n = self.n as isize;
-        while let Some((x, y, direction)) = queue.pop_front() {
+    fn mikami_tabuchi_search(
+        &self,
+        ctx: &mut RouterContext,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+    ) -> Result<Option<ChangeSet>, RouteError> {
+        ctx.begin();
+        ctx.visit_source(x1, y1);
+        for direction in &ctx.order(x1, y1) {
+            ctx.line_queue.push_back((x1 as u32, y1 as u32, *direction));
+        }
+        while let Some((x, y, direction)) = ctx.line_queue.pop_front() {
+            ctx.expand()?;
+            let (x, y) = (x as usize, y as usize);
             if x == x2 && y == y2 {
                 // found
-                let mut direction = dir_map[x][y].unwrap();
-                let mut cur_x = x;
-                let mut cur_y = y;
-                while cur_x != x1 || cur_y != y1 {
-                    let (dx, dy) = direction.offset();
-                    let new_x = (cur_x as isize + dx) as usize;
-                    let new_y = (cur_y as isize + dy) as usize;
-                    if new_x == x1 && new_y == y1 {
-                        break;
-                    }
-
-                    let new_direction = dir_map[new_x][new_y].unwrap();
-                    changes.push((
-                        new_x,
-                        new_y,
-                        new_direction.get_new_cell_state(&direction, &self.map[new_x][new_y]),
-                    ));
-                    cur_x = new_x;
-                    cur_y = new_y;
-                    direction = new_direction;
-                }
-
-                changes.push((x1, y1, CellState::Blocked));
-                changes.push((x2, y2, CellState::Blocked));
-                return Some(ChangeSet { changes: changes });
+                return Ok(Some(ctx.path(self, x2, y2)));
             }
 
-            let x = x as isize;
-            let y: isize = y as isize;
-            let (dx, dy) = direction.offset();
-            let mut new_x = x + dx;
-            let mut new_y = y + dy;
-            while 0 <= new_x && new_x < m && 0 <= new_y && new_y < n {
-                if dir_map[new_x as usize][new_y as usize].is_none()
-                    && direction.can_cross(&self.map[new_x as usize][new_y as usize])
+            let (mut cur_x, mut cur_y) = (x, y);
+            while let Some((new_x, new_y)) = ctx.neighbor(cur_x, cur_y, direction) {
+                let crossable = direction.can_cross(&self.map[(new_x, new_y)], ctx.planar);
+                if !ctx.visited(new_x, new_y)
+                    && (crossable || (new_x, new_y) == (x2, y2))
+                    && self.can_connect(cur_x, cur_y, new_x, new_y, direction)
                 {
-                    dir_map[new_x as usize][new_y as usize] = Some(direction.opposite());
-                    match direction {
-                        L | R => {
-                            queue.push_back((new_x as usize, new_y as usize, U));
-                            queue.push_back((new_x as usize, new_y as usize, D));
-                        }
-                        U | D => {
-                            queue.push_back((new_x as usize, new_y as usize, L));
-                            queue.push_back((new_x as usize, new_y as usize, R));
-                        }
+                    ctx.visit(new_x, new_y, direction.opposite());
+                    if !crossable {
+                        // an existing terminal ends the line
+                        ctx.line_queue
+                            .push_back((new_x as u32, new_y as u32, direction));
+                        break;
+                    }
+                    let turns = direction.perpendicular();
+                    for turn in ctx
+                        .order(new_x, new_y)
+                        .iter()
+                        .filter(|turn| turns.contains(turn))
+                    {
+                        ctx.line_queue
+                            .push_back((new_x as u32, new_y as u32, *turn));
                     }
-                    new_x += dx;
-                    new_y += dy;
+                    cur_x = new_x;
+                    cur_y = new_y;
                 } else {
                     break;
                 }
             }
         }
-        None
-    }
-
-    /// Mikami-Tabuchi's algorithm, line search
-    pub fn mikami_tabuchi_mut(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) -> bool {
-        match self.mikami_tabuchi(x1, y1, x2, y2) {
-            Some(changes) => {
-                self.apply(&changes);
-                true
-            }
-            None => false,
-        }
+        Ok(None)
     }
 
-    /// Mikami-Tabuchi's algorithm for multiple points, line search
-    pub fn mikami_tabuchi_multi(&self, points: &Points) -> Option<ChangeSet> {
-        use Direction::*;
-        let mut changes = vec![];
+    /// Mikami-Tabuchi's algorithm for multiple points, line search,
+    /// reusing the scratch buffers in `ctx` within the limits of `options`
+    pub fn mikami_tabuchi_multi_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        points: &Points,
+    ) -> Result<ChangeSet, RouteError> {
         let points = points.get();
-        if points.len() == 0 {
-            return Some(ChangeSet { changes });
+        if points.is_empty() {
+            return Ok(ChangeSet::default());
         } else if points.len() == 1 {
-            changes.push((points[0].0, points[0].1, CellState::Blocked));
-            return Some(ChangeSet { changes });
+            return Ok(self.single_terminal(points[0].0, points[0].1));
         }
+        self.search_window(ctx, options, &points, |maze, ctx| {
+            maze.mikami_tabuchi_multi_search(ctx, &points)
+        })
+    }
 
+    fn mikami_tabuchi_multi_search(
+        &self,
+        ctx: &mut RouterContext,
+        points: &[(usize, usize)],
+    ) -> Result<Option<ChangeSet>, RouteError> {
+        let mut changes = vec![];
+        let mut terminals = Terminals::default();
         let mut dest_points = BTreeSet::new();
         for point in &points[1..] {
             dest_points.insert(point);
         }
 
-        let mut queue = VecDeque::new();
-        let mut dir_map = vec![vec![None; self.n]; self.m];
-        let mut new_map = self.map.clone();
+        ctx.begin();
         let (x1, y1) = points[0];
-        dir_map[x1][y1] = Some(L);
-        queue.push_back((x1, y1, L));
-        queue.push_back((x1, y1, R));
-        queue.push_back((x1, y1, U));
-        queue.push_back((x1, y1, D));
-        new_map[x1][y1] = CellState::Blocked;
-        changes.push((x1, y1, CellState::Blocked));
+        ctx.visit_source(x1, y1);
+        for direction in &ctx.order(x1, y1) {
+            ctx.line_queue.push_back((x1 as u32, y1 as u32, *direction));
+        }
+        ctx.write(x1, y1, CellState::Terminal);
+        terminals.add(self, &mut changes, x1, y1);
 
-        let m = self.m as isize;
-        let n = self.n as isize;
-        while let Some((x, y, direction)) = queue.pop_front() {
+        while let Some((x, y, direction)) = ctx.line_queue.pop_front() {
+            ctx.expand()?;
+            let (x, y) = (x as usize, y as usize);
             if dest_points.contains(&(x, y)) {
                 // found
                 dest_points.remove(&(x, y));
-                new_map[x][y] = CellState::Blocked;
-                changes.push((x, y, CellState::Blocked));
+                ctx.write(x, y, CellState::Terminal);
+                terminals.add(self, &mut changes, x, y);
 
-                let mut direction = dir_map[x][y].unwrap();
+                let mut direction = ctx.direction(x, y);
+                terminals.connect(x, y, direction);
                 let mut cur_x = x;
                 let mut cur_y = y;
                 while cur_x != x1 || cur_y != y1 {
                     let (dx, dy) = direction.offset();
                     let new_x = (cur_x as isize + dx) as usize;
                     let new_y = (cur_y as isize + dy) as usize;
-                    if new_map[new_x][new_y] == CellState::Blocked {
+                    if ctx.cell(self, new_x, new_y) == CellState::Terminal {
+                        terminals.connect(new_x, new_y, direction.opposite());
                         break;
                     }
 
-                    let new_direction = dir_map[new_x][new_y].unwrap();
+                    let new_direction = ctx.direction(new_x, new_y);
                     let new_cell_state =
-                        new_direction.get_new_cell_state(&direction, &new_map[new_x][new_y]);
-                    new_map[new_x][new_y] = new_cell_state;
+                        new_direction.get_new_cell_state(&direction, &ctx.cell(self, new_x, new_y));
+                    if ctx.planar && new_cell_state == CellState::Cross {
+                        // a junction of four wires cannot be told apart from
+                        // a crossing
+                        return Ok(None);
+                    }
+                    ctx.write(new_x, new_y, new_cell_state);
                     changes.push((new_x, new_y, new_cell_state));
                     cur_x = new_x;
                     cur_y = new_y;
                     direction = new_direction;
                 }
 
-                if dest_points.len() == 0 {
-                    return Some(ChangeSet { changes: changes });
+                if dest_points.is_empty() {
+                    return Ok(Some(terminals.finish(changes)));
                 }
             }
 
-            let x = x as isize;
-            let y: isize = y as isize;
-            let (dx, dy) = direction.offset();
-            let mut new_x = x + dx;
-            let mut new_y = y + dy;
-            while 0 <= new_x && new_x < m && 0 <= new_y && new_y < n {
-                if dir_map[new_x as usize][new_y as usize].is_none()
-                    && direction.can_cross(&new_map[new_x as usize][new_y as usize])
+            let (mut cur_x, mut cur_y) = (x, y);
+            while let Some((new_x, new_y)) = ctx.neighbor(cur_x, cur_y, direction) {
+                let crossable = direction.can_cross(&ctx.cell(self, new_x, new_y), ctx.planar);
+                if !ctx.visited(new_x, new_y)
+                    && (crossable || dest_points.contains(&(new_x, new_y)))
+                    && self.can_connect(cur_x, cur_y, new_x, new_y, direction)
                 {
-                    dir_map[new_x as usize][new_y as usize] = Some(direction.opposite());
-                    match direction {
-                        L | R => {
-                            queue.push_back((new_x as usize, new_y as usize, U));
-                            queue.push_back((new_x as usize, new_y as usize, D));
-                        }
-                        U | D => {
-                            queue.push_back((new_x as usize, new_y as usize, L));
-                            queue.push_back((new_x as usize, new_y as usize, R));
-                        }
+                    ctx.visit(new_x, new_y, direction.opposite());
+                    if !crossable {
+                        // an existing terminal ends the line
+                        ctx.line_queue
+                            .push_back((new_x as u32, new_y as u32, direction));
+                        break;
                     }
-                    new_x += dx;
-                    new_y += dy;
+                    let turns = direction.perpendicular();
+                    for turn in ctx
+                        .order(new_x, new_y)
+                        .iter()
+                        .filter(|turn| turns.contains(turn))
+                    {
+                        ctx.line_queue
+                            .push_back((new_x as u32, new_y as u32, *turn));
+                    }
+                    cur_x = new_x;
+                    cur_y = new_y;
                 } else {
                     break;
                 }
             }
         }
-        None
+        Ok(None)
+    }
+}
+
+#[wasm_bindgen]
+impl Maze {
+    /// Mikami-Tabuchi's algorithm, line search
+    pub fn mikami_tabuchi(&self, x1: usize, y1: usize, x2: usize, y2: usize) -> Option<ChangeSet> {
+        let options = RouteOptions::default();
+        self.mikami_tabuchi_with(&mut RouterContext::new(self), &options, x1, y1, x2, y2)
+            .ok()
+    }
+
+    /// Mikami-Tabuchi's algorithm, line search
+    pub fn mikami_tabuchi_mut(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) -> bool {
+        match self.mikami_tabuchi(x1, y1, x2, y2) {
+            Some(changes) => {
+                self.apply(&changes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Mikami-Tabuchi's algorithm for multiple points, line search
+    pub fn mikami_tabuchi_multi(&self, points: &Points) -> Option<ChangeSet> {
+        let options = RouteOptions::default();
+        self.mikami_tabuchi_multi_with(&mut RouterContext::new(self), &options, points)
+            .ok()
     }
 
     /// Mikami-Tabuchi's algorithm for multiple points, line search
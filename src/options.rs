@@ -0,0 +1,591 @@
+//! Search limits shared by all routers
+use super::*;
+use std::time::{Duration, Instant};
+
+/// Options limiting how much work a router may do.
+///
+/// The default searches the whole maze without any budget.
+#[derive(Clone, Debug, Default)]
+pub struct RouteOptions {
+    /// Only search inside the bounding box of the terminals grown by this
+    /// many cells. When no route is found inside the window, the margin is
+    /// doubled until the window covers the whole maze.
+    pub margin: Option<usize>,
+    /// Give up after expanding this many cells, summed over all windows
+    pub max_expansions: Option<usize>,
+    /// Give up after this much wall-clock time. Not available on
+    /// `wasm32-unknown-unknown`, where `Instant::now` panics.
+    pub time_budget: Option<Duration>,
+    /// How to choose between equally good moves
+    pub tie_break: TieBreak,
+    /// Never cross a wire, producing no `CellState::Cross`. Also forced by
+    /// `Maze::set_planar`.
+    pub planar: bool,
+    /// Costs minimized by the Lee variants minimizing crossings or the edge
+    /// effect
+    pub weights: Weights,
+    /// Pin recorded in the new terminals of the route. The route follows
+    /// the rule of this pin (`Maze::set_rule`) and of the nets it joins.
+    pub net: u32,
+    /// Pins of the nets whose wires `Weights::coupling` penalizes running
+    /// next to, every other net if empty
+    pub aggressors: Vec<u32>,
+}
+
+/// Costs of a route, summed over its cells. The default only counts
+/// crossings or the edge effect, so the length breaks ties.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Weights {
+    /// Cost of every step
+    pub length: usize,
+    /// Cost of crossing a wire, for `Maze::lee_minimum_crossing_with`
+    pub crossing: usize,
+    /// Cost of every neighbouring obstacle or bent wire, for
+    /// `Maze::lee_minimum_edge_effect_with`
+    pub edge: usize,
+    /// Cost of every neighbouring wire of an aggressor net (see
+    /// `RouteOptions::aggressors`), for both Lee variants
+    pub coupling: usize,
+}
+
+impl Default for Weights {
+    fn default() -> Weights {
+        Weights {
+            length: 0,
+            crossing: 1,
+            edge: 1,
+            coupling: 0,
+        }
+    }
+}
+
+/// How routers choose between equally good moves.
+///
+/// Every router expands the neighbours of a cell in the order given by the
+/// policy. Routers keeping a priority queue (Lee minimum crossing and edge
+/// effect, Hadlock) pop cells of equal cost in the order they were pushed,
+/// except for `LowerCoordinates` and `Random`, which also order those cells.
+/// With the same policy, maze and nets the routes are always the same.
+/// Single trunk Steiner trees do not search and ignore the policy.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TieBreak {
+    /// Expand neighbours in this order. The default is `[L, R, U, D]`.
+    Directions([Direction; 4]),
+    /// Keep going in the same direction first, then `[L, R, U, D]`
+    Straight,
+    /// Prefer the cell with the lower x, then the lower y coordinate
+    LowerCoordinates,
+    /// Shuffle the neighbours and cells of equal cost with a random
+    /// generator seeded with this value at the start of every net
+    Random(u64),
+}
+
+impl Default for TieBreak {
+    fn default() -> TieBreak {
+        use Direction::*;
+        TieBreak::Directions([L, R, U, D])
+    }
+}
+
+/// Reason why a router did not return a route
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RouteError {
+    /// The whole maze was searched and the terminals cannot be connected
+    Unroutable,
+    /// The search stopped at `max_expansions` or `time_budget`
+    BudgetExceeded,
+    /// The router does not route this kind of net
+    Unsupported,
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::Unroutable => write!(f, "unroutable"),
+            RouteError::BudgetExceeded => write!(f, "search budget exceeded"),
+            RouteError::Unsupported => write!(f, "unsupported net"),
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
+
+/// An inclusive rectangle of cells
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Rect {
+    pub x1: usize,
+    pub y1: usize,
+    pub x2: usize,
+    pub y2: usize,
+}
+
+impl Rect {
+    /// The whole m x n maze
+    pub fn full(m: usize, n: usize) -> Rect {
+        Rect {
+            x1: 0,
+            y1: 0,
+            x2: m.saturating_sub(1),
+            y2: n.saturating_sub(1),
+        }
+    }
+
+    /// Bounding box of a non-empty list of points
+    pub fn bounding(points: &[(usize, usize)]) -> Rect {
+        let (x, y) = points[0];
+        let mut rect = Rect {
+            x1: x,
+            y1: y,
+            x2: x,
+            y2: y,
+        };
+        for (x, y) in &points[1..] {
+            rect.x1 = min(rect.x1, *x);
+            rect.y1 = min(rect.y1, *y);
+            rect.x2 = max(rect.x2, *x);
+            rect.y2 = max(rect.y2, *y);
+        }
+        rect
+    }
+
+    /// Whether the two rectangles share a cell
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x1 <= other.x2 && other.x1 <= self.x2 && self.y1 <= other.y2 && other.y1 <= self.y2
+    }
+
+    /// Grow by `margin` cells on every side, clipped to the m x n maze
+    pub fn grow(&self, margin: usize, m: usize, n: usize) -> Rect {
+        Rect {
+            x1: self.x1.saturating_sub(margin),
+            y1: self.y1.saturating_sub(margin),
+            x2: min(self.x2.saturating_add(margin), m - 1),
+            y2: min(self.y2.saturating_add(margin), n - 1),
+        }
+    }
+}
+
+/// Expansions between two looks at the clock
+const CLOCK_INTERVAL: usize = 1024;
+
+/// Budget of one routing call
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Budget {
+    expansions: usize,
+    max_expansions: Option<usize>,
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    pub fn new(options: &RouteOptions) -> Budget {
+        Budget {
+            expansions: 0,
+            max_expansions: options.max_expansions,
+            deadline: options.time_budget.map(|budget| Instant::now() + budget),
+        }
+    }
+
+    /// Count one expanded cell, failing when the budget is used up
+    #[inline]
+    pub fn expand(&mut self) -> Result<(), RouteError> {
+        self.expansions += 1;
+        if let Some(max_expansions) = self.max_expansions {
+            if self.expansions > max_expansions {
+                return Err(RouteError::BudgetExceeded);
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if self.expansions.is_multiple_of(CLOCK_INTERVAL) && Instant::now() >= deadline {
+                return Err(RouteError::BudgetExceeded);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tie-breaking state of one routing call
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Ties {
+    policy: TieBreak,
+    /// number of keys handed out
+    sequence: u64,
+    /// xorshift state
+    random: u64,
+}
+
+impl Ties {
+    pub fn new(options: &RouteOptions) -> Ties {
+        let random = match options.tie_break {
+            // xorshift gets stuck at zero
+            TieBreak::Random(seed) => seed ^ 0x9e37_79b9_7f4a_7c15,
+            _ => 0,
+        };
+        Ties {
+            policy: options.tie_break,
+            sequence: 0,
+            random: max(random, 1),
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        let mut x = self.random;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.random = x;
+        x
+    }
+
+    /// Order to expand the neighbours of a cell reached going `travel`
+    pub fn order(&mut self, travel: Option<Direction>) -> [Direction; 4] {
+        use Direction::*;
+        match self.policy {
+            TieBreak::Directions(order) => order,
+            TieBreak::Straight => {
+                let mut order = [L, R, U, D];
+                if let Some(travel) = travel {
+                    order[..=travel.index()].rotate_right(1);
+                }
+                order
+            }
+            TieBreak::LowerCoordinates => [L, D, U, R],
+            TieBreak::Random(_) => {
+                let mut order = [L, R, U, D];
+                for i in (1..order.len()).rev() {
+                    let j = (self.next_random() % (i as u64 + 1)) as usize;
+                    order.swap(i, j);
+                }
+                order
+            }
+        }
+    }
+
+    /// Key ordering cells of equal cost in a priority queue, the smallest
+    /// first
+    pub fn key(&mut self, x: usize, y: usize) -> u64 {
+        match self.policy {
+            TieBreak::LowerCoordinates => (x as u64) << 32 | y as u64,
+            TieBreak::Random(_) => self.next_random(),
+            _ => {
+                self.sequence += 1;
+                self.sequence
+            }
+        }
+    }
+}
+
+impl Maze {
+    /// Run `search` inside growing windows around `terminals` as configured
+    /// by `options`. `search` returns `Ok(None)` when the current window
+    /// (`ctx.window`) holds no route.
+    pub(crate) fn search_window<F>(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        terminals: &[(usize, usize)],
+        search: F,
+    ) -> Result<ChangeSet, RouteError>
+    where
+        F: FnMut(&Maze, &mut RouterContext) -> Result<Option<ChangeSet>, RouteError>,
+    {
+        self.search_window_ruled(ctx, options, terminals, &[options.net], search)
+    }
+
+    /// Like `search_window`, following the strictest rule of `pins`
+    pub(crate) fn search_window_ruled<F>(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        terminals: &[(usize, usize)],
+        pins: &[u32],
+        mut search: F,
+    ) -> Result<ChangeSet, RouteError>
+    where
+        F: FnMut(&Maze, &mut RouterContext) -> Result<Option<ChangeSet>, RouteError>,
+    {
+        // a terminal on a wire would cut it
+        if terminals
+            .iter()
+            .any(|(x, y)| !matches!(self.map[(*x, *y)], CellState::Empty | CellState::Terminal))
+        {
+            return Err(RouteError::Unroutable);
+        }
+        let full = Rect::full(self.m, self.n);
+        ctx.start(self, options);
+        if let Some((rule, keep_out)) = self.keep_out(pins, terminals) {
+            if terminals.iter().any(|cell| keep_out[*cell]) {
+                return Err(RouteError::Unroutable);
+            }
+            // wires of other nets are too close to cross
+            ctx.planar |= rule != Rule::default();
+            ctx.keep_out = Some(keep_out);
+        }
+        if options.weights.coupling > 0 {
+            ctx.coupling = Some(self.coupling(terminals, &options.aggressors));
+        }
+        let mut margin = options.margin;
+        loop {
+            let window = self.window(margin, terminals);
+            ctx.window = window;
+            if let Some(mut changes) = search(self, ctx)? {
+                for (x, y, terminal) in &mut changes.terminals {
+                    if self.map[(*x, *y)] != CellState::Terminal {
+                        terminal.pin = options.net;
+                    }
+                }
+                return Ok(changes);
+            }
+            if window == full {
+                return Err(RouteError::Unroutable);
+            }
+            if ctx.confined {
+                ctx.escaped = true;
+                return Err(RouteError::Unroutable);
+            }
+            margin = margin.map(|margin| max(1, margin.saturating_mul(2)));
+        }
+    }
+
+    /// Search window around `terminals` grown by `margin`, the whole maze
+    /// without a margin
+    pub(crate) fn window(&self, margin: Option<usize>, terminals: &[(usize, usize)]) -> Rect {
+        match margin {
+            Some(margin) if !terminals.is_empty() => {
+                Rect::bounding(terminals).grow(margin, self.m, self.n)
+            }
+            _ => Rect::full(self.m, self.n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn unroutable() {
+        // (0, 0) is walled in
+        let mut maze = Maze::new(10, 10);
+        maze.fill_mut(1, 0, 1, 1);
+        maze.fill_mut(0, 1, 0, 1);
+        let mut ctx = RouterContext::new(&maze);
+        let options = RouteOptions {
+            margin: Some(1),
+            ..RouteOptions::default()
+        };
+        assert_eq!(
+            maze.lee_with(&mut ctx, &options, 0, 0, 9, 9).err(),
+            Some(RouteError::Unroutable)
+        );
+        assert_eq!(
+            maze.hadlock_with(&mut ctx, &options, 0, 0, 9, 9).err(),
+            Some(RouteError::Unroutable)
+        );
+        assert_eq!(
+            maze.mikami_tabuchi_with(&mut ctx, &options, 0, 0, 9, 9)
+                .err(),
+            Some(RouteError::Unroutable)
+        );
+
+        let options = RouteOptions {
+            max_expansions: Some(10),
+            ..RouteOptions::default()
+        };
+        assert_eq!(
+            maze.lee_with(&mut ctx, &options, 9, 9, 0, 0).err(),
+            Some(RouteError::BudgetExceeded)
+        );
+        assert_eq!(
+            maze.lee_minimum_crossing_with(&mut ctx, &options, 9, 9, 0, 0)
+                .err(),
+            Some(RouteError::BudgetExceeded)
+        );
+
+        let options = RouteOptions {
+            time_budget: Some(Duration::from_secs(0)),
+            ..RouteOptions::default()
+        };
+        let maze = Maze::new(100, 100);
+        let points = Points::new(&[(0, 0), (99, 0), (99, 99)]);
+        assert_eq!(
+            maze.mikami_tabuchi_multi_with(&mut ctx, &options, &points)
+                .err(),
+            Some(RouteError::BudgetExceeded)
+        );
+        assert_eq!(
+            maze.lee_with(&mut ctx, &options, 0, 0, 99, 99).err(),
+            Some(RouteError::BudgetExceeded)
+        );
+    }
+
+    fn route_all(options: &RouteOptions) -> Vec<String> {
+        let mut routes = vec![];
+        for router in 0..4 {
+            let mut maze = Maze::new(5, 4);
+            let mut ctx = RouterContext::new(&maze);
+            let changes = match router {
+                0 => maze.lee_with(&mut ctx, options, 0, 0, 4, 3),
+                1 => maze.lee_minimum_crossing_with(&mut ctx, options, 0, 0, 4, 3),
+                2 => maze.hadlock_with(&mut ctx, options, 0, 0, 4, 3),
+                _ => maze.mikami_tabuchi_with(&mut ctx, options, 0, 0, 4, 3),
+            };
+            maze.apply(&changes.unwrap());
+            routes.push(maze.to_string());
+        }
+        routes
+    }
+
+    #[test]
+    fn tie_break() {
+        use Direction::*;
+        let right_first = "....╻\n....┃\n....┃\n╺━━━┛\n";
+        let up_first = "┏━━━╸\n┃....\n┃....\n╹....\n";
+        for (tie_break, expected) in &[
+            (TieBreak::default(), right_first),
+            (TieBreak::Directions([U, R, L, D]), up_first),
+            (TieBreak::Straight, right_first),
+            (TieBreak::LowerCoordinates, up_first),
+        ] {
+            let options = RouteOptions {
+                tie_break: *tie_break,
+                ..RouteOptions::default()
+            };
+            for route in route_all(&options) {
+                assert_eq!(&route, expected, "{:?}", tie_break);
+            }
+        }
+
+        let seeded = |seed| RouteOptions {
+            tie_break: TieBreak::Random(seed),
+            ..RouteOptions::default()
+        };
+        assert_eq!(route_all(&seeded(1)), route_all(&seeded(1)));
+        assert_ne!(route_all(&seeded(1)), route_all(&seeded(2)));
+    }
+
+    #[test]
+    fn window_grows() {
+        // a wall forces a detour outside the bounding box of the terminals
+        let mut maze = Maze::new(20, 20);
+        maze.fill_mut(5, 0, 5, 15);
+        let mut ctx = RouterContext::new(&maze);
+        let options = RouteOptions {
+            margin: Some(0),
+            ..RouteOptions::default()
+        };
+        let changes = maze.lee_with(&mut ctx, &options, 3, 3, 7, 3).unwrap();
+        assert_eq!(changes.changes.len(), 2 + 2 * 13 + 3);
+        maze.apply(&changes);
+        assert!(maze.verify());
+        println!("{}", maze);
+    }
+
+    #[test]
+    fn planar() {
+        let mut maze = Maze::new(5, 5);
+        assert!(maze.lee_mut(0, 2, 4, 2));
+        let mut ctx = RouterContext::new(&maze);
+        let options = RouteOptions {
+            planar: true,
+            ..RouteOptions::default()
+        };
+        assert_eq!(
+            maze.lee_with(&mut ctx, &options, 2, 0, 2, 4).err(),
+            Some(RouteError::Unroutable)
+        );
+        let points = Points::new(&[(2, 0), (2, 4)]);
+        assert_eq!(
+            maze.multi_terminal_with(&mut ctx, &options, &points)
+                .map_err(RouteError::from)
+                .err(),
+            Some(RouteError::Unroutable)
+        );
+        assert!(maze.lee(2, 0, 2, 4).is_some());
+
+        assert!(maze.stst(&points).is_some());
+        assert_eq!(
+            maze.stst_with(&options, &points).err(),
+            Some(RouteError::Unroutable)
+        );
+
+        let mut maze = Maze::new(5, 5);
+        maze.set_planar(true);
+        assert!(maze.lee_mut(0, 2, 4, 2));
+        assert!(!maze.mikami_tabuchi_mut(2, 0, 2, 4));
+        assert!(!maze.lee_minimum_crossing_mut(2, 0, 2, 4));
+    }
+
+    quickcheck! {
+        fn qc_tie_break_shortest(m: usize, n: usize, seed: u64, x1: usize, y1: usize, x2: usize, y2: usize) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+            let mut maze = Maze::new(m, n);
+            let mut ctx = RouterContext::new(&maze);
+            let options = RouteOptions {
+                tie_break: TieBreak::Random(seed),
+                ..RouteOptions::default()
+            };
+            let random = maze.lee_with(&mut ctx, &options, x1, y1, x2, y2).unwrap();
+            let default = maze.lee(x1, y1, x2, y2).unwrap();
+            maze.apply(&random);
+            random.changes.len() == default.changes.len() && maze.verify()
+        }
+
+        fn qc_window_same_as_full(m: usize, n: usize, margin: usize, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            let mut ctx = RouterContext::new(&Maze::new(m, n));
+            let options = RouteOptions {
+                margin: Some(margin % 10),
+                ..RouteOptions::default()
+            };
+            let mut maze = Maze::new(m, n);
+            for (x1, y1, x2, y2) in points {
+                let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+                let windowed = maze.lee_with(&mut ctx, &options, x1, y1, x2, y2);
+                if windowed.is_ok() != maze.lee(x1, y1, x2, y2).is_some() {
+                    return false;
+                }
+                if let Ok(changes) = windowed {
+                    maze.apply(&changes);
+                }
+            }
+            maze.verify()
+        }
+
+        fn qc_planar_no_cross(m: usize, n: usize, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            let mut maze = Maze::new(m, n);
+            maze.set_planar(true);
+            for (i, (x1, y1, x2, y2)) in points.into_iter().enumerate() {
+                let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+                match i % 3 {
+                    0 => maze.two_terminal_mut(x1, y1, x2, y2),
+                    1 => maze.lee_minimum_crossing_mut(x1, y1, x2, y2),
+                    _ => maze.multi_terminal_mut(&Points::new(&[(x1, y1), (x2, y2), (x1, y2), (x2, y1)])),
+                };
+            }
+            maze.verify() && !maze.to_string().contains(&CellState::Cross.to_string())
+        }
+    }
+}
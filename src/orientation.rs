@@ -0,0 +1,151 @@
+//! Regions restricted to horizontal or vertical wires
+use super::*;
+
+/// Directions wires may run in through a cell
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Orientation {
+    /// Any direction, the default
+    Any,
+    /// Left and right only
+    Horizontal,
+    /// Up and down only
+    Vertical,
+}
+
+impl Orientation {
+    /// Whether a wire may leave a cell of this orientation to `direction`
+    #[inline]
+    pub(crate) fn allows(&self, direction: Direction) -> bool {
+        use Direction::*;
+        match self {
+            Orientation::Any => true,
+            Orientation::Horizontal => direction == L || direction == R,
+            Orientation::Vertical => direction == U || direction == D,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Maze {
+    /// Only let wires run in `orientation` through all points in rectangle
+    /// (x1, y1) to (x2, y2), `Orientation::Any` lifts the restriction
+    pub fn restrict_mut(
+        &mut self,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+        orientation: Orientation,
+    ) {
+        let from_x = min(x1, x2);
+        let to_x = max(x1, x2);
+        let from_y = min(y1, y2);
+        let to_y = max(y1, y2);
+        assert!(to_x < self.m);
+        assert!(to_y < self.n);
+        let (m, n) = (self.m, self.n);
+        let orientations = self
+            .orientations
+            .get_or_insert_with(|| Grid::new(m, n, Orientation::Any));
+        for i in from_x..(to_x + 1) {
+            for j in from_y..(to_y + 1) {
+                orientations[(i, j)] = orientation;
+            }
+        }
+    }
+
+    /// Only let wires run in `orientation` through all points in rectangle
+    /// (x1, y1) to (x2, y2)
+    pub fn restrict(
+        &self,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+        orientation: Orientation,
+    ) -> Maze {
+        let mut inst = self.clone();
+        inst.restrict_mut(x1, y1, x2, y2, orientation);
+        inst
+    }
+
+    /// Directions wires may run in through (x, y)
+    pub fn orientation(&self, x: usize, y: usize) -> Orientation {
+        match &self.orientations {
+            Some(orientations) => orientations[(x, y)],
+            None => Orientation::Any,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn orientation() {
+        let mut maze = Maze::new(4, 4);
+        maze.restrict_mut(0, 1, 3, 2, Orientation::Horizontal);
+        assert_eq!(maze.orientation(2, 2), Orientation::Horizontal);
+        assert_eq!(maze.lee(0, 0, 0, 3), None);
+        assert_eq!(maze.stst(&Points::new(&[(0, 0), (0, 3)])), None);
+        assert!(maze.stst(&Points::new(&[(0, 1), (2, 1)])).is_some());
+
+        maze.restrict_mut(3, 1, 3, 2, Orientation::Any);
+        let routes = [
+            maze.lee(0, 0, 0, 3),
+            maze.lee_minimum_crossing(0, 0, 0, 3),
+            maze.lee_minimum_edge_effect(0, 0, 0, 3),
+            maze.hadlock(0, 0, 0, 3),
+            maze.mikami_tabuchi(0, 0, 0, 3),
+            maze.lee_multi(&Points::new(&[(0, 0), (0, 3)])),
+            maze.mikami_tabuchi_multi(&Points::new(&[(0, 0), (0, 3)])),
+        ];
+        for changes in &routes {
+            let mut routed = maze.clone();
+            routed.apply(changes.as_ref().unwrap());
+            assert!(routed.verify());
+            // the only way runs up the unrestricted column
+            assert_eq!(routed.get(3, 1), CellState::UD);
+            assert_eq!(routed.get(3, 2), CellState::UD);
+        }
+
+        // a vertical-only column cuts every horizontal wire
+        let maze = Maze::new(3, 3).restrict(1, 0, 1, 2, Orientation::Vertical);
+        assert_eq!(maze.lee(0, 1, 2, 1), None);
+    }
+
+    quickcheck! {
+        fn qc_orientation(m: usize, n: usize, regions: Vec<(usize, usize, usize, usize, bool)>, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            let mut maze = Maze::new(m, n);
+            for (x1, y1, x2, y2, horizontal) in regions {
+                let orientation = if horizontal {
+                    Orientation::Horizontal
+                } else {
+                    Orientation::Vertical
+                };
+                maze.restrict_mut(x1 % m, y1 % n, x2 % m, y2 % n, orientation);
+            }
+            for (i, (x1, y1, x2, y2)) in points.into_iter().enumerate() {
+                let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+                if i % 2 == 0 {
+                    maze.two_terminal_mut(x1, y1, x2, y2);
+                } else {
+                    maze.stst_mut(&Points::new(&[(x1, y1), (x2, y2)]));
+                }
+            }
+            maze.verify()
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! Routing differential pairs and bundles of wires side by side
+use super::*;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+
+/// Outcome of `Maze::diff_pair_with`
+#[derive(Clone, Debug)]
+pub struct PairReport {
+    /// routes of both nets
+    pub changes: ChangeSet,
+    /// steps of the positive net
+    pub p_length: usize,
+    /// steps of the negative net
+    pub n_length: usize,
+    /// steps of the outer net around corners, where it runs without the
+    /// other net beside it
+    pub uncoupled: usize,
+}
+
+/// Wires of a bundle at a line of adjacent cells: the first one at (x, y),
+/// every other one next to the one before in `offset`, going `travel`
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct BundleState {
+    x: usize,
+    y: usize,
+    offset: Direction,
+    travel: Direction,
+}
+
+/// How a bundle got to a state, with the cells entered by each wire
+struct BundleMove {
+    from: BundleState,
+    cells: Vec<Vec<(usize, usize)>>,
+}
+
+/// Paths of the wires of a bundle, with the steps they run uncoupled
+pub(crate) struct Bundle {
+    pub(crate) paths: Vec<Vec<(usize, usize)>>,
+    pub(crate) uncoupled: usize,
+}
+
+impl Maze {
+    /// Route a differential pair: the positive net from (x1, y1) to (x2, y2)
+    /// of `p` and the negative net from (x1, y1) to (x2, y2) of `n`, whose
+    /// ends must be adjacent. Both wires run side by side, and around
+    /// corners the outer one takes two more steps. Searches pair states
+    /// minimizing the steps of the longer wire, reusing the scratch buffers
+    /// in `ctx` within the limits of `options`. The wires cross nothing.
+    pub fn diff_pair_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        p: (usize, usize, usize, usize),
+        n: (usize, usize, usize, usize),
+    ) -> Result<PairReport, RouteError> {
+        let bundle = self.bundle_with(
+            ctx,
+            options,
+            &[(p.0, p.1), (n.0, n.1)],
+            &[(p.2, p.3), (n.2, n.3)],
+        )?;
+        let changes = self.bundle_changes(&bundle, options.net);
+        Ok(PairReport {
+            changes,
+            p_length: bundle.paths[0].len() - 1,
+            n_length: bundle.paths[1].len() - 1,
+            uncoupled: bundle.uncoupled,
+        })
+    }
+
+    /// Route wires from `starts` to `ends`, both lines of adjacent cells in
+    /// order, side by side. Around a corner the i-th wire from the inside
+    /// takes 2i more steps. Minimizes the steps of the outermost wire,
+    /// within the window, rule and limits of `options` like the other
+    /// routers.
+    pub(crate) fn bundle_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        starts: &[(usize, usize)],
+        ends: &[(usize, usize)],
+    ) -> Result<Bundle, RouteError> {
+        use Direction::*;
+        let k = starts.len();
+        let line = |cells: &[(usize, usize)]| {
+            [L, R, U, D].iter().copied().find(|direction| {
+                cells
+                    .windows(2)
+                    .all(|w| self.map.neighbor(w[0].0, w[0].1, *direction) == Some(w[1]))
+            })
+        };
+        let (start_offset, end_offset) = match (line(starts), line(ends)) {
+            (Some(start), Some(end)) if k >= 2 && ends.len() == k => (start, end),
+            _ => return Err(RouteError::Unroutable),
+        };
+        let pins: BTreeSet<(usize, usize)> = starts.iter().chain(ends).copied().collect();
+        if pins.iter().any(|cell| self.map[*cell] != CellState::Empty) || pins.len() != 2 * k {
+            return Err(RouteError::Unroutable);
+        }
+        let all: Vec<(usize, usize)> = pins.iter().copied().collect();
+        let mut found = None;
+        self.search_window(ctx, options, &all, |maze, ctx| {
+            found = maze.bundle_search(ctx, starts, ends, start_offset, end_offset)?;
+            Ok(found.as_ref().map(|_| ChangeSet::default()))
+        })?;
+        found.ok_or(RouteError::Unroutable)
+    }
+
+    /// Search the states of a bundle from `starts` to `ends` within the
+    /// window of `ctx`. The states outnumber the cells, so they are kept
+    /// apart from the scratch buffers of `ctx`.
+    fn bundle_search(
+        &self,
+        ctx: &mut RouterContext,
+        starts: &[(usize, usize)],
+        ends: &[(usize, usize)],
+        start_offset: Direction,
+        end_offset: Direction,
+    ) -> Result<Option<Bundle>, RouteError> {
+        use Direction::*;
+        let k = starts.len();
+        let pins: BTreeSet<(usize, usize)> = starts.iter().chain(ends).copied().collect();
+        // cells entered by a wire going `directions` from `cell`
+        let walk = |ctx: &RouterContext,
+                    cell: (usize, usize),
+                    directions: &[Direction],
+                    end: (usize, usize)| {
+            let mut cells = vec![];
+            let mut cur = cell;
+            for (i, direction) in directions.iter().enumerate() {
+                let next = ctx.neighbor(cur.0, cur.1, *direction)?;
+                // only the last cell may be a pin, the end of the wire
+                let last = i + 1 == directions.len();
+                if self.map[next] != CellState::Empty
+                    || pins.contains(&next) && !(last && next == end)
+                    || !self.can_connect(cur.0, cur.1, next.0, next.1, *direction)
+                {
+                    return None;
+                }
+                cells.push(next);
+                cur = next;
+            }
+            Some(cells)
+        };
+
+        let mut heap = BinaryHeap::new();
+        // cheapest cost and move found to every state
+        let mut moves: HashMap<BundleState, (usize, Option<BundleMove>)> = HashMap::new();
+        for travel in &[L, R, U, D] {
+            if travel.index() / 2 != start_offset.index() / 2 {
+                let state = BundleState {
+                    x: starts[0].0,
+                    y: starts[0].1,
+                    offset: start_offset,
+                    travel: *travel,
+                };
+                moves.insert(state, (0, None));
+                heap.push(Reverse((0, ctx.key(state.x, state.y), state)));
+            }
+        }
+        let mut done = BTreeSet::new();
+        while let Some(Reverse((cost, _, state))) = heap.pop() {
+            if !done.insert(state) {
+                continue;
+            }
+            ctx.expand()?;
+            if (state.x, state.y) == ends[0] {
+                if state.offset == end_offset {
+                    if let Some(bundle) = self.bundle_paths(&moves, state, k) {
+                        return Ok(Some(bundle));
+                    }
+                }
+                // the wires end here
+                continue;
+            }
+
+            let (t, offset) = (state.travel, state.offset);
+            let mut wires = vec![(state.x, state.y)];
+            while wires.len() < k {
+                let (x, y) = wires[wires.len() - 1];
+                match ctx.neighbor(x, y, offset) {
+                    Some(cell) => wires.push(cell),
+                    None => break,
+                }
+            }
+            if wires.len() < k {
+                continue;
+            }
+            // straight on, or turning towards the first or the last wire:
+            // then wire i goes as many steps along `t` as it is away from the
+            // inner wire, and one more along the new direction
+            let moves_to = [
+                (None, offset, t),
+                (Some(0), t, offset.opposite()),
+                (Some(k - 1), t.opposite(), offset),
+            ];
+            for (inner, new_offset, new_travel) in moves_to.iter() {
+                let mut cells = vec![];
+                for (i, wire) in wires.iter().enumerate() {
+                    let directions = match inner {
+                        None => vec![t],
+                        Some(inner) => {
+                            let before = max(i, *inner) - min(i, *inner);
+                            let mut directions = vec![t; before];
+                            directions.extend(vec![*new_travel; before + 1]);
+                            directions
+                        }
+                    };
+                    match walk(ctx, *wire, &directions, ends[i]) {
+                        Some(wire_cells) => cells.push(wire_cells),
+                        None => break,
+                    }
+                }
+                let entered: BTreeSet<&(usize, usize)> = cells.iter().flatten().collect();
+                if cells.len() < k || entered.len() != cells.iter().map(Vec::len).sum::<usize>() {
+                    continue;
+                }
+                let steps = cells.iter().map(Vec::len).max().unwrap();
+                let (x, y) = cells[0][cells[0].len() - 1];
+                let next = BundleState {
+                    x,
+                    y,
+                    offset: *new_offset,
+                    travel: *new_travel,
+                };
+                if done.contains(&next)
+                    || moves
+                        .get(&next)
+                        .is_some_and(|(old, _)| *old <= cost + steps)
+                {
+                    continue;
+                }
+                moves.insert(
+                    next,
+                    (cost + steps, Some(BundleMove { from: state, cells })),
+                );
+                heap.push(Reverse((cost + steps, ctx.key(x, y), next)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Paths of the wires of a bundle up to `state`, `None` if the wires run
+    /// into each other
+    fn bundle_paths(
+        &self,
+        moves: &HashMap<BundleState, (usize, Option<BundleMove>)>,
+        mut state: BundleState,
+        k: usize,
+    ) -> Option<Bundle> {
+        let mut paths = vec![vec![]; k];
+        let mut uncoupled = 0;
+        while let Some((_, Some(bundle_move))) = moves.get(&state) {
+            let lengths = bundle_move.cells.iter().map(Vec::len);
+            uncoupled += lengths.clone().max().unwrap() - lengths.min().unwrap();
+            for (path, cells) in paths.iter_mut().zip(&bundle_move.cells) {
+                path.extend(cells.iter().rev());
+            }
+            state = bundle_move.from;
+        }
+        let mut cell = (state.x, state.y);
+        for path in paths.iter_mut() {
+            path.push(cell);
+            path.reverse();
+            cell = self
+                .map
+                .neighbor(cell.0, cell.1, state.offset)
+                .unwrap_or(cell);
+        }
+        let cells: BTreeSet<&(usize, usize)> = paths.iter().flatten().collect();
+        if cells.len() != paths.iter().map(Vec::len).sum::<usize>() {
+            return None;
+        }
+        Some(Bundle { paths, uncoupled })
+    }
+
+    /// Changes routing every wire of `bundle`, recording `net` in the
+    /// terminals
+    pub(crate) fn bundle_changes(&self, bundle: &Bundle, net: u32) -> ChangeSet {
+        let mut changes = ChangeSet::default();
+        for path in &bundle.paths {
+            let wire = self.path_changes(&ChangeSet::default(), path);
+            changes.changes.extend(wire.changes);
+            changes.terminals.extend(wire.terminals);
+        }
+        changes.set_pin(net);
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn diff_pair() {
+        let maze = Maze::new(6, 6);
+        let mut ctx = RouterContext::new(&maze);
+        let options = RouteOptions::default();
+        // straight across
+        let report = maze
+            .diff_pair_with(&mut ctx, &options, (0, 1, 5, 1), (0, 2, 5, 2))
+            .unwrap();
+        assert_eq!((report.p_length, report.n_length), (5, 5));
+        assert_eq!(report.uncoupled, 0);
+
+        // the terminals record the net
+        let net = RouteOptions {
+            net: 3,
+            ..RouteOptions::default()
+        };
+        let report = maze
+            .diff_pair_with(&mut ctx, &net, (0, 1, 5, 1), (0, 2, 5, 2))
+            .unwrap();
+        let mut routed = maze.clone();
+        routed.apply(&report.changes);
+        for (x, y) in &[(0, 1), (5, 1), (0, 2), (5, 2)] {
+            assert_eq!(routed.terminal(*x, *y).unwrap().pin, 3);
+        }
+
+        // the negative wire is outside the corner
+        let report = maze
+            .diff_pair_with(&mut ctx, &options, (0, 1, 4, 5), (0, 0, 5, 5))
+            .unwrap();
+        let mut routed = maze.clone();
+        routed.apply(&report.changes);
+        println!("{}", routed);
+        assert!(routed.verify());
+        assert_eq!((report.p_length, report.n_length), (8, 10));
+        assert_eq!(report.uncoupled, 2);
+
+        // the window of `options` keeps the search next to the pins
+        let wide = Maze::new(30, 30);
+        let mut ctx = RouterContext::new(&wide);
+        let budget = RouteOptions {
+            max_expansions: Some(20),
+            ..RouteOptions::default()
+        };
+        assert_eq!(
+            wide.diff_pair_with(&mut ctx, &budget, (10, 10, 15, 10), (10, 11, 15, 11))
+                .err(),
+            Some(RouteError::BudgetExceeded)
+        );
+        let window = RouteOptions {
+            margin: Some(0),
+            ..budget
+        };
+        let report = wide
+            .diff_pair_with(&mut ctx, &window, (10, 10, 15, 10), (10, 11, 15, 11))
+            .unwrap();
+        assert_eq!((report.p_length, report.n_length), (5, 5));
+        let random = RouteOptions {
+            tie_break: TieBreak::Random(7),
+            ..window
+        };
+        let report = wide
+            .diff_pair_with(&mut ctx, &random, (10, 10, 15, 10), (10, 11, 15, 11))
+            .unwrap();
+        assert_eq!((report.p_length, report.n_length), (5, 5));
+
+        // a gap of one cell fits a single wire, but not the pair
+        let maze = maze.fill(3, 0, 3, 2).fill(3, 4, 3, 5);
+        assert!(maze.lee(0, 1, 5, 1).is_some());
+        assert_eq!(
+            maze.diff_pair_with(&mut ctx, &options, (0, 1, 5, 1), (0, 2, 5, 2))
+                .err(),
+            Some(RouteError::Unroutable)
+        );
+    }
+
+    quickcheck! {
+        fn qc_diff_pair(m: usize, n: usize, blocks: Vec<(usize, usize)>, points: (usize, usize, usize, usize), sides: (bool, bool)) -> bool {
+            if m < 2 || n < 2 {
+                return true;
+            }
+            // check oom
+            if m > 30 || n > 30 {
+                return true
+            }
+
+            let mut maze = Maze::new(m, n);
+            for (x, y) in blocks {
+                maze.fill_mut(x % m, y % n, x % m, y % n);
+            }
+            // the negative pins next to the positive ones
+            let pins = |x: usize, y: usize, vertical: bool| {
+                if vertical {
+                    ((x % m, y % (n - 1)), (x % m, y % (n - 1) + 1))
+                } else {
+                    ((x % (m - 1), y % n), (x % (m - 1) + 1, y % n))
+                }
+            };
+            let ((px1, py1), (nx1, ny1)) = pins(points.0, points.1, sides.0);
+            let ((px2, py2), (nx2, ny2)) = pins(points.2, points.3, sides.1);
+            let mut ctx = RouterContext::new(&maze);
+            // a pair always goes straight across an empty maze
+            if sides.0 && sides.1 && px1 != px2 && py1 == py2 {
+                let empty = Maze::new(m, n);
+                let length = px1.abs_diff(px2);
+                if !empty
+                    .diff_pair_with(&mut ctx, &RouteOptions::default(), (px1, py1, px2, py2), (nx1, ny1, nx2, ny2))
+                    .is_ok_and(|report| report.p_length == length && report.n_length == length)
+                {
+                    return false;
+                }
+            }
+            match maze.diff_pair_with(&mut ctx, &RouteOptions::default(), (px1, py1, px2, py2), (nx1, ny1, nx2, ny2)) {
+                Ok(report) => {
+                    let mut routed = maze.clone();
+                    routed.apply(&report.changes);
+                    routed.verify()
+                        && report.changes.crossings() == 0
+                        && report.changes.len() == report.p_length + report.n_length + 2
+                        && report.uncoupled >= max(report.p_length, report.n_length) - min(report.p_length, report.n_length)
+                }
+                Err(_) => true,
+            }
+        }
+    }
+}
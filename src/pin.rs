@@ -0,0 +1,348 @@
+//! Pins spanning several cells
+//!
+//! Only Lee's and Hadlock's algorithms route between pins. Mikami-Tabuchi,
+//! the single trunk Steiner tree and the multi-terminal routers grow their
+//! routes from single cells, so nets given as `Points` still have one cell
+//! per terminal; `Pin::from_points` turns such cells into a pin.
+use super::*;
+use std::collections::BTreeSet;
+
+/// A pin that wires may attach to at any of its access cells
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pin {
+    /// Recorded in the terminal placed at the access cell used
+    pub id: u32,
+    cells: Vec<(usize, usize)>,
+}
+
+impl Pin {
+    /// Pin with the given access cells
+    pub fn with_cells(id: u32, cells: &[(usize, usize)]) -> Pin {
+        Pin {
+            id,
+            cells: Vec::from(cells),
+        }
+    }
+
+    /// Deduped and sorted access cells
+    pub fn cells(&self) -> Vec<(usize, usize)> {
+        let mut res = self.cells.clone();
+        res.sort();
+        res.dedup();
+        res
+    }
+}
+
+#[wasm_bindgen]
+impl Pin {
+    /// Pin without access cells
+    #[wasm_bindgen(constructor)]
+    pub fn new(id: u32) -> Pin {
+        Pin { id, cells: vec![] }
+    }
+
+    /// Pin covering the rectangle (x1, y1) to (x2, y2)
+    pub fn rectangle(id: u32, x1: usize, y1: usize, x2: usize, y2: usize) -> Pin {
+        let mut pin = Pin::new(id);
+        pin.add_rectangle(x1, y1, x2, y2);
+        pin
+    }
+
+    /// Pin with access cells at `points`
+    pub fn from_points(id: u32, points: &Points) -> Pin {
+        Pin::with_cells(id, &points.get())
+    }
+
+    /// Add an access cell
+    pub fn add(&mut self, x: usize, y: usize) {
+        self.cells.push((x, y));
+    }
+
+    /// Add all cells in rectangle (x1, y1) to (x2, y2) as access cells
+    pub fn add_rectangle(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) {
+        for x in min(x1, x2)..=max(x1, x2) {
+            for y in min(y1, y2)..=max(y1, y2) {
+                self.cells.push((x, y));
+            }
+        }
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.cells.contains(&(x, y))
+    }
+}
+
+impl Maze {
+    /// Lee's algorithm between two pins, connecting the closest pair of
+    /// access cells, reusing the scratch buffers in `ctx` within the limits
+    /// of `options`
+    pub fn lee_pins_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        from: &Pin,
+        to: &Pin,
+    ) -> Result<ChangeSet, RouteError> {
+        self.pins_with(ctx, options, from, to, |maze, ctx, sources, targets| {
+            maze.lee_search(ctx, sources, targets)
+        })
+    }
+
+    /// Hadlock's algorithm between two pins, guided by the distance to the
+    /// bounding box of `to`, reusing the scratch buffers in `ctx` within the
+    /// limits of `options`
+    pub fn hadlock_pins_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        from: &Pin,
+        to: &Pin,
+    ) -> Result<ChangeSet, RouteError> {
+        self.pins_with(ctx, options, from, to, |maze, ctx, sources, targets| {
+            maze.hadlock_search(ctx, sources, targets)
+        })
+    }
+
+    /// Search from the free access cells of `from` to those of `to`,
+    /// recording the pins in the terminals of the route and reserving the
+    /// access cells left over
+    fn pins_with<F>(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        from: &Pin,
+        to: &Pin,
+        mut search: F,
+    ) -> Result<ChangeSet, RouteError>
+    where
+        F: FnMut(
+            &Maze,
+            &mut RouterContext,
+            &[(usize, usize)],
+            &BTreeSet<(usize, usize)>,
+        ) -> Result<Option<ChangeSet>, RouteError>,
+    {
+        let access = |pin: &Pin| -> Vec<(usize, usize)> {
+            pin.cells()
+                .into_iter()
+                .filter(|(x, y)| self.free(pin, *x, *y))
+                .collect()
+        };
+        let sources = access(from);
+        let targets: BTreeSet<(usize, usize)> = access(to).into_iter().collect();
+        if sources.is_empty() || targets.is_empty() {
+            return Err(RouteError::Unroutable);
+        }
+
+        let mut cells = sources.clone();
+        cells.extend(targets.iter().copied());
+        let pins = [options.net, from.id, to.id];
+        let mut changes = self.search_window_ruled(ctx, options, &cells, &pins, |maze, ctx| {
+            search(maze, ctx, &sources, &targets)
+        })?;
+        for (x, y, terminal) in &mut changes.terminals {
+            terminal.pin = if targets.contains(&(*x, *y)) {
+                to.id
+            } else {
+                from.id
+            };
+        }
+        let reserved = self.place_pin(from).compose(&self.place_pin(to));
+        Ok(reserved.compose(&changes))
+    }
+
+    /// Whether a wire of `pin` may attach at (x, y)
+    fn free(&self, pin: &Pin, x: usize, y: usize) -> bool {
+        match self.map[(x, y)] {
+            CellState::Empty => true,
+            CellState::Terminal => {
+                pin.id != 0 && self.terminal(x, y).unwrap_or_default().pin == pin.id
+            }
+            _ => false,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Maze {
+    /// Unconnected terminals of `pin` on its empty access cells, keeping
+    /// other nets out of the pin
+    pub fn place_pin(&self, pin: &Pin) -> ChangeSet {
+        let mut changes = vec![];
+        let mut terminals = vec![];
+        for (x, y) in pin.cells() {
+            if self.map[(x, y)] == CellState::Empty {
+                changes.push((x, y, CellState::Terminal));
+                terminals.push((x, y, Terminal::new(pin.id)));
+            }
+        }
+        ChangeSet { changes, terminals }
+    }
+
+    pub fn place_pin_mut(&mut self, pin: &Pin) {
+        let changes = self.place_pin(pin);
+        self.apply(&changes);
+    }
+
+    /// Lee's algorithm between two pins
+    pub fn lee_pins(&self, from: &Pin, to: &Pin) -> Option<ChangeSet> {
+        let options = RouteOptions::default();
+        self.lee_pins_with(&mut RouterContext::new(self), &options, from, to)
+            .ok()
+    }
+
+    pub fn lee_pins_mut(&mut self, from: &Pin, to: &Pin) -> bool {
+        match self.lee_pins(from, to) {
+            Some(changes) => {
+                self.apply(&changes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Hadlock's algorithm between two pins
+    pub fn hadlock_pins(&self, from: &Pin, to: &Pin) -> Option<ChangeSet> {
+        let options = RouteOptions::default();
+        self.hadlock_pins_with(&mut RouterContext::new(self), &options, from, to)
+            .ok()
+    }
+
+    pub fn hadlock_pins_mut(&mut self, from: &Pin, to: &Pin) -> bool {
+        match self.hadlock_pins(from, to) {
+            Some(changes) => {
+                self.apply(&changes);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl ChangeSet {
+    /// Cells where wires attach to the terminals of `pin`
+    pub fn access(&self, pin: u32) -> Vec<(usize, usize)> {
+        self.terminals
+            .iter()
+            .filter(|(_, _, terminal)| {
+                terminal.pin == pin && terminal.directions().iter().any(|side| *side)
+            })
+            .map(|(x, y, _)| (*x, *y))
+            .collect()
+    }
+}
+
+#[wasm_bindgen]
+impl ChangeSet {
+    /// Access cells used by `pin`
+    pub fn access_points(&self, pin: u32) -> Points {
+        Points::new(&self.access(pin))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn pins() {
+        let mut maze = Maze::new(8, 5);
+        maze.fill_mut(3, 0, 3, 3);
+        let from = Pin::rectangle(1, 0, 0, 1, 4);
+        let mut to = Pin::new(2);
+        to.add(7, 0);
+        to.add(5, 2);
+        let changes = maze.lee_pins(&from, &to).unwrap();
+        // the path over the wall starts at the top of `from`
+        assert_eq!(changes.access(1), vec![(1, 4)]);
+        assert_eq!(changes.access(2), vec![(5, 2)]);
+        // seven cells of wire, the other access cells are reserved
+        assert_eq!(changes.len(), 7 + 9 + 1);
+        assert_eq!(
+            maze.hadlock_pins(&from, &to).unwrap().access(2),
+            vec![(5, 2)]
+        );
+        maze.apply(&changes);
+        println!("{}", maze);
+        assert!(maze.verify());
+        assert_eq!(maze.terminal(1, 4).unwrap().pin, 1);
+        assert_eq!(maze.get(0, 4), CellState::Terminal);
+        assert_eq!(maze.terminal(0, 4).unwrap().pin, 1);
+
+        // other nets stay out of the pins
+        assert_eq!(maze.lee(0, 0, 7, 4), None);
+        let used = Pin::with_cells(3, &[(1, 4), (3, 0)]);
+        assert_eq!(maze.lee_pins(&used, &to), None);
+
+        // the pin attaches another net at one of its reserved cells
+        let other = Pin::with_cells(4, &[(2, 0)]);
+        let changes = maze.lee_pins(&from, &other).unwrap();
+        assert_eq!(changes.access(1), vec![(1, 0)]);
+        maze.apply(&changes);
+        println!("{}", maze);
+        assert!(maze.verify());
+        assert_eq!(maze.terminal(1, 0).unwrap().pin, 1);
+    }
+
+    #[test]
+    fn shared_cell() {
+        // pins sharing (1, 0) are joined there without a wire, and the
+        // shared cell records the pin routed to
+        let maze = Maze::new(4, 1);
+        let from = Pin::with_cells(1, &[(0, 0), (1, 0)]);
+        let to = Pin::with_cells(2, &[(1, 0), (2, 0)]);
+        let expected = ChangeSet {
+            changes: vec![
+                (0, 0, CellState::Terminal),
+                (1, 0, CellState::Terminal),
+                (2, 0, CellState::Terminal),
+            ],
+            terminals: vec![
+                (0, 0, Terminal::new(1)),
+                (1, 0, Terminal::new(2)),
+                (2, 0, Terminal::new(2)),
+            ],
+        };
+        assert_eq!(maze.lee_pins(&from, &to), Some(expected.clone()));
+        assert_eq!(maze.hadlock_pins(&from, &to), Some(expected));
+    }
+
+    quickcheck! {
+        fn qc_pins_same_as_cells(m: usize, n: usize, from: Vec<(usize, usize)>, to: Vec<(usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            // a route between pins is never longer than one between any of
+            // their cells
+            let maze = Maze::new(m, n);
+            let cells = |cells: Vec<(usize, usize)>| -> Vec<(usize, usize)> {
+                cells.into_iter().map(|(x, y)| (x % m, y % n)).collect()
+            };
+            let from = Pin::with_cells(1, &cells(from));
+            let to = Pin::with_cells(2, &cells(to));
+            let route = match maze.lee_pins(&from, &to) {
+                Some(route) => route,
+                None => return from.cells().is_empty() || to.cells().is_empty(),
+            };
+            let mut result = maze.clone();
+            result.apply(&route);
+            let (source, target) = (from.cells()[0], to.cells()[0]);
+            let single = maze.lee(source.0, source.1, target.0, target.1).unwrap();
+            let reserved = maze.place_pin(&from).compose(&maze.place_pin(&to)).len();
+            let wire = route.len() + route.access(1).len() + route.access(2).len() - reserved;
+            // pins sharing a cell are joined without a wire
+            let shared = from.cells().iter().any(|(x, y)| to.contains(*x, *y));
+            result.verify()
+                && (route.access(2).len() == 1 || shared && wire == 0)
+                && wire <= single.len()
+                && maze.hadlock_pins(&from, &to).is_some()
+        }
+    }
+}
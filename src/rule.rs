@@ -0,0 +1,480 @@
+//! Wire width and spacing rules
+use super::*;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+
+/// Width and spacing of the wires of a net
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RuleFields")]
+pub struct Rule {
+    /// Tracks taken by a wire, at least one. A wide wire claims a band of
+    /// cells centred on its path, one more to the right and up for even
+    /// widths.
+    width: usize,
+    /// Free cells kept between the band and other nets or obstacles
+    spacing: usize,
+}
+
+/// A rule as written, checked before it becomes a `Rule`
+#[derive(Deserialize)]
+struct RuleFields {
+    width: usize,
+    spacing: usize,
+}
+
+impl TryFrom<RuleFields> for Rule {
+    type Error = String;
+
+    fn try_from(fields: RuleFields) -> Result<Rule, String> {
+        if fields.width == 0 {
+            return Err(String::from("wire width must be at least 1"));
+        }
+        Ok(Rule {
+            width: fields.width,
+            spacing: fields.spacing,
+        })
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Rule {
+        Rule {
+            width: 1,
+            spacing: 0,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Rule {
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize, spacing: usize) -> Rule {
+        assert!(width > 0);
+        Rule { width, spacing }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn spacing(&self) -> usize {
+        self.spacing
+    }
+}
+
+impl Rule {
+    /// Rule obeying both rules
+    pub fn strictest(&self, other: &Rule) -> Rule {
+        Rule {
+            width: max(self.width, other.width),
+            spacing: max(self.spacing, other.spacing),
+        }
+    }
+
+    /// Lowest and highest offset of the band from the path
+    fn band(&self) -> (usize, usize) {
+        ((self.width - 1) / 2, self.width / 2)
+    }
+}
+
+/// A broken design rule found by `Maze::drc`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// The band of the wire at (x, y) leaves the maze
+    OffGrid(usize, usize),
+    /// The bands of two nets, or of a net and an obstacle, at these cells
+    /// are closer than the spacing of the nets
+    Spacing((usize, usize), (usize, usize)),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::OffGrid(x, y) => write!(f, "wire at ({}, {}) leaves the maze", x, y),
+            Violation::Spacing((x1, y1), (x2, y2)) => {
+                write!(f, "({}, {}) too close to ({}, {})", x1, y1, x2, y2)
+            }
+        }
+    }
+}
+
+/// Connected wires and terminals
+#[derive(Clone)]
+pub(crate) struct NetCells {
+    pub(crate) cells: Vec<(usize, usize)>,
+    /// part of every cell: 0 for all, 1 for the horizontal and 2 for the
+    /// vertical wire of a crossing
+    pub(crate) parts: Vec<u8>,
+    /// pins of its terminals, sorted and each once
+    pub(crate) pins: Vec<u32>,
+    /// strictest rule of `pins`
+    pub(crate) rule: Rule,
+}
+
+#[wasm_bindgen]
+impl Maze {
+    /// Route the nets with terminals of `pin` following `rule`. A net
+    /// connecting terminals of several pins follows the strictest rule.
+    pub fn set_rule(&mut self, pin: u32, rule: Rule) {
+        self.nets = OnceLock::new();
+        if rule == Rule::default() {
+            self.rules.remove(&pin);
+        } else {
+            self.rules.insert(pin, rule);
+        }
+    }
+
+    /// Rule of the nets with terminals of `pin`
+    pub fn rule(&self, pin: u32) -> Rule {
+        self.rules.get(&pin).copied().unwrap_or_default()
+    }
+
+    /// Whether `drc` finds no violation
+    pub fn passes_drc(&self) -> bool {
+        self.drc().is_empty()
+    }
+}
+
+impl Maze {
+    /// Design rule check: every wire band stays inside the maze and keeps
+    /// the spacing of its net from other nets and obstacles. Nets without a
+    /// rule may cross each other, as routers do without rules.
+    pub fn drc(&self) -> Vec<Violation> {
+        let mut violations = vec![];
+        let nets = self.nets();
+        // nets whose band covers each cell
+        let mut owners: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+        for (i, net) in nets.iter().enumerate() {
+            for (x, y) in &net.cells {
+                match self.band(&net.rule, *x, *y) {
+                    Some(band) => {
+                        for cell in band {
+                            owners.entry(cell).or_default().push(i);
+                        }
+                    }
+                    None => violations.push(Violation::OffGrid(*x, *y)),
+                }
+            }
+        }
+
+        let max_spacing = nets.iter().map(|net| net.rule.spacing).max().unwrap_or(0);
+        for (&(x, y), here) in &owners {
+            for other_x in x.saturating_sub(max_spacing)..=min(x + max_spacing, self.m - 1) {
+                for other_y in y.saturating_sub(max_spacing)..=min(y + max_spacing, self.n - 1) {
+                    let distance = max(x.abs_diff(other_x), y.abs_diff(other_y));
+                    let too_close = |a: &Rule, b: &Rule| {
+                        (*a != Rule::default() || *b != Rule::default())
+                            && distance <= max(a.spacing, b.spacing)
+                    };
+                    let obstacle = self.map[(other_x, other_y)] == CellState::Blocked
+                        && here
+                            .iter()
+                            .any(|i| too_close(&nets[*i].rule, &Rule::default()));
+                    // two nets are compared from the lower cell
+                    let wires = (other_x, other_y) >= (x, y)
+                        && owners.get(&(other_x, other_y)).is_some_and(|there| {
+                            here.iter().any(|i| {
+                                there
+                                    .iter()
+                                    .any(|j| i != j && too_close(&nets[*i].rule, &nets[*j].rule))
+                            })
+                        });
+                    if obstacle || wires {
+                        violations.push(Violation::Spacing((x, y), (other_x, other_y)));
+                    }
+                }
+            }
+        }
+        violations
+    }
+
+    /// Strictest rule of `pins`
+    pub(crate) fn strictest_rule(&self, pins: &[u32]) -> Rule {
+        pins.iter().fold(Rule::default(), |rule, pin| {
+            rule.strictest(&self.rule(*pin))
+        })
+    }
+
+    /// Cells claimed by a wire at (x, y) following `rule`, `None` if some
+    /// are outside the maze
+    fn band(&self, rule: &Rule, x: usize, y: usize) -> Option<Vec<(usize, usize)>> {
+        let (low, high) = rule.band();
+        if x < low || y < low || x + high >= self.m || y + high >= self.n {
+            return None;
+        }
+        let mut cells = vec![];
+        for i in (x - low)..=(x + high) {
+            for j in (y - low)..=(y + high) {
+                cells.push((i, j));
+            }
+        }
+        Some(cells)
+    }
+
+    /// Wires and terminals grouped into nets, cached until the maze changes
+    pub(crate) fn nets(&self) -> &[NetCells] {
+        self.nets.get_or_init(|| self.find_nets())
+    }
+
+    /// Group wires and terminals into nets by following their connections.
+    /// A crossing belongs to both nets running through it.
+    fn find_nets(&self) -> Vec<NetCells> {
+        use Direction::*;
+        // a cell and which part of it: 0 for all, 1 for the horizontal and
+        // 2 for the vertical wire of a crossing
+        let part_of = |(x, y): (usize, usize), direction: Direction| match self.map[(x, y)] {
+            CellState::Cross if direction == L || direction == R => 1,
+            CellState::Cross => 2,
+            _ => 0,
+        };
+        let mut seen = BTreeSet::new();
+        let mut nets = vec![];
+        for x in 0..self.m {
+            for y in 0..self.n {
+                let parts: &[u8] = match self.map[(x, y)] {
+                    CellState::Empty | CellState::Blocked => &[],
+                    CellState::Cross => &[1, 2],
+                    _ => &[0],
+                };
+                for part in parts {
+                    if !seen.insert((x, y, *part)) {
+                        continue;
+                    }
+                    let mut cells = vec![];
+                    let mut parts = vec![];
+                    let mut pins = vec![];
+                    let mut stack = vec![(x, y, *part)];
+                    while let Some((x, y, part)) = stack.pop() {
+                        cells.push((x, y));
+                        parts.push(part);
+                        if let Some(terminal) = self.terminal(x, y) {
+                            pins.push(terminal.pin);
+                        }
+                        let sides = self.directions(x, y);
+                        for direction in &[L, R, U, D] {
+                            let horizontal = *direction == L || *direction == R;
+                            if !sides[direction.index()]
+                                || part == 1 && !horizontal
+                                || part == 2 && horizontal
+                            {
+                                continue;
+                            }
+                            if let Some(neighbor) = self.map.neighbor(x, y, *direction) {
+                                let connected = self.directions(neighbor.0, neighbor.1)
+                                    [direction.opposite().index()];
+                                let part = part_of(neighbor, *direction);
+                                if connected && seen.insert((neighbor.0, neighbor.1, part)) {
+                                    stack.push((neighbor.0, neighbor.1, part));
+                                }
+                            }
+                        }
+                    }
+                    pins.sort_unstable();
+                    pins.dedup();
+                    let rule = self.strictest_rule(&pins);
+                    nets.push(NetCells {
+                        cells,
+                        parts,
+                        pins,
+                        rule,
+                    });
+                }
+            }
+        }
+        nets
+    }
+
+    /// Cells the path of a net may not enter so that its band keeps the
+    /// spacing from other nets and obstacles. The net follows the strictest
+    /// rule of `pins` and of the nets it joins at `terminals`. `None` when
+    /// the maze has no rules.
+    pub(crate) fn keep_out(
+        &self,
+        pins: &[u32],
+        terminals: &[(usize, usize)],
+    ) -> Option<(Rule, Grid<bool>)> {
+        if self.rules.is_empty() {
+            return None;
+        }
+        let (own, other): (Vec<&NetCells>, Vec<&NetCells>) = self
+            .nets()
+            .iter()
+            .partition(|net| net.cells.iter().any(|cell| terminals.contains(cell)));
+        let rule = own.iter().fold(self.strictest_rule(pins), |rule, net| {
+            rule.strictest(&net.rule)
+        });
+
+        let mut keep_out = Grid::new(self.m, self.n, false);
+        let (low, high) = rule.band();
+        // paths whose band covers (x, y) closer than `spacing`
+        let mut cover = |x: usize, y: usize, spacing: usize| {
+            for i in x.saturating_sub(high + spacing)..=min(x + low + spacing, self.m - 1) {
+                for j in y.saturating_sub(high + spacing)..=min(y + low + spacing, self.n - 1) {
+                    keep_out[(i, j)] = true;
+                }
+            }
+        };
+        let default = rule == Rule::default();
+        for x in 0..self.m {
+            for y in 0..self.n {
+                if !default && self.map[(x, y)] == CellState::Blocked {
+                    cover(x, y, rule.spacing);
+                }
+            }
+        }
+        for net in &other {
+            if default && net.rule == Rule::default() {
+                continue;
+            }
+            let spacing = max(rule.spacing, net.rule.spacing);
+            for (x, y) in &net.cells {
+                let (net_low, net_high) = net.rule.band();
+                for i in x.saturating_sub(net_low)..=min(x + net_high, self.m - 1) {
+                    for j in y.saturating_sub(net_low)..=min(y + net_high, self.n - 1) {
+                        cover(i, j, spacing);
+                    }
+                }
+            }
+        }
+        for x in 0..self.m {
+            for y in 0..self.n {
+                if self.band(&rule, x, y).is_none() {
+                    keep_out[(x, y)] = true;
+                }
+            }
+        }
+        Some((rule, keep_out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn spacing() {
+        let mut maze = Maze::new(5, 3);
+        maze.two_terminal_mut(0, 1, 4, 1);
+        assert!(maze.lee(0, 0, 4, 0).is_some());
+
+        // (0, 0) is next to the first net
+        maze.set_rule(1, Rule::new(1, 1));
+        let mut ctx = RouterContext::new(&maze);
+        let options = RouteOptions {
+            net: 1,
+            ..RouteOptions::default()
+        };
+        assert_eq!(
+            maze.lee_with(&mut ctx, &options, 0, 0, 4, 0).err(),
+            Some(RouteError::Unroutable)
+        );
+        assert!(maze.passes_drc());
+
+        // a default net may not come closer either
+        let mut maze = Maze::new(5, 5);
+        maze.set_rule(1, Rule::new(1, 1));
+        let changes = maze.lee_with(&mut ctx, &options, 0, 2, 4, 2).unwrap();
+        assert_eq!(changes.terminals[0].2.pin, 1);
+        maze.apply(&changes);
+        assert_eq!(maze.lee(0, 1, 4, 1), None);
+        assert!(maze.lee(0, 0, 4, 0).is_some());
+
+        // a rule set after routing
+        maze.two_terminal_mut(0, 0, 4, 0);
+        maze.set_rule(0, Rule::new(1, 2));
+        assert_eq!(maze.drc()[0], Violation::Spacing((0, 0), (0, 2)));
+    }
+
+    #[test]
+    fn width() {
+        // a wall with a gap of one cell at y = 1
+        let mut maze = Maze::new(7, 7).fill(3, 0, 3, 0).fill(3, 2, 3, 6);
+        maze.set_rule(2, Rule::new(3, 0));
+        let mut ctx = RouterContext::new(&maze);
+        let options = RouteOptions {
+            net: 2,
+            ..RouteOptions::default()
+        };
+        assert!(maze.lee(1, 3, 5, 3).is_some());
+        assert_eq!(
+            maze.lee_with(&mut ctx, &options, 1, 3, 5, 3).err(),
+            Some(RouteError::Unroutable)
+        );
+
+        // a gap of three cells
+        maze.clean_mut(3, 2, 3, 3);
+        let routes = [
+            maze.lee_with(&mut ctx, &options, 1, 3, 5, 3),
+            maze.hadlock_with(&mut ctx, &options, 1, 3, 5, 3),
+            maze.mikami_tabuchi_with(&mut ctx, &options, 1, 3, 5, 3),
+            maze.lee_multi_with(&mut ctx, &options, &Points::new(&[(1, 3), (5, 3)])),
+        ];
+        for changes in &routes {
+            let mut routed = maze.clone();
+            routed.apply(changes.as_ref().unwrap());
+            println!("{}", routed);
+            assert!(routed.verify());
+            assert!(routed.passes_drc());
+            assert_eq!(routed.get(3, 2), CellState::LR);
+        }
+        // the band would leave the maze
+        assert_eq!(
+            maze.lee_with(&mut ctx, &options, 0, 3, 5, 3).err(),
+            Some(RouteError::Unroutable)
+        );
+    }
+
+    #[test]
+    fn rule_fields() {
+        let rule: Rule = serde_json::from_str(r#"{"width":3,"spacing":1}"#).unwrap();
+        assert_eq!(rule, Rule::new(3, 1));
+        assert_eq!(
+            serde_json::to_string(&rule).unwrap(),
+            r#"{"width":3,"spacing":1}"#
+        );
+        assert!(serde_json::from_str::<Rule>(r#"{"width":0,"spacing":1}"#).is_err());
+    }
+
+    quickcheck! {
+        fn qc_rules(m: usize, n: usize, blocks: Vec<(usize, usize)>, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 30 || n > 30 {
+                return true
+            }
+
+            let mut maze = Maze::new(m, n);
+            for (x, y) in blocks {
+                maze.fill_mut(x % m, y % n, x % m, y % n);
+            }
+            maze.set_rule(1, Rule::new(2, 0));
+            maze.set_rule(2, Rule::new(1, 1));
+            let mut ctx = RouterContext::new(&maze);
+            for (i, (x1, y1, x2, y2)) in points.into_iter().enumerate() {
+                let (x1, y1, x2, y2) = (x1 % m, y1 % n, x2 % m, y2 % n);
+                // joining a net would change its rule
+                if maze.get(x1, y1) != CellState::Empty || maze.get(x2, y2) != CellState::Empty {
+                    continue;
+                }
+                let options = RouteOptions {
+                    net: i as u32 % 3,
+                    ..RouteOptions::default()
+                };
+                let changes = match i % 4 {
+                    0 => maze.lee_with(&mut ctx, &options, x1, y1, x2, y2),
+                    1 => maze.hadlock_with(&mut ctx, &options, x1, y1, x2, y2),
+                    2 => maze.mikami_tabuchi_with(&mut ctx, &options, x1, y1, x2, y2),
+                    _ => maze.stst_with(&options, &Points::new(&[(x1, y1), (x2, y2)])),
+                };
+                if let Ok(changes) = changes {
+                    maze.apply(&maze.straighten(&changes));
+                }
+            }
+            maze.verify() && maze.passes_drc()
+        }
+    }
+}
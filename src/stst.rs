@@ -2,20 +2,50 @@
 
 use super::*;
 
-#[wasm_bindgen]
 impl Maze {
-    /// Single Trunk Steiner Tree Algorithm
-    pub fn stst(&self, points: &Points) -> Option<ChangeSet> {
+    /// Single Trunk Steiner Tree Algorithm, without crossing wires or
+    /// joining four wires in one cell if `options` or the maze are planar
+    pub fn stst_with(
+        &self,
+        options: &RouteOptions,
+        points: &Points,
+    ) -> Result<ChangeSet, RouteError> {
         use CellState::*;
+        let mut planar = options.planar || self.planar;
         let points = points.get();
-        if points.len() == 0 {
-            return Some(ChangeSet { changes: vec![] });
+        if points.is_empty() {
+            return Ok(ChangeSet::default());
         }
+        // a terminal on a wire would cut it
         for (x, y) in &points {
-            if self.map[*x][*y] != Empty {
-                return None;
+            if !matches!(self.map[(*x, *y)], Empty | Terminal) {
+                return Err(RouteError::Unroutable);
+            }
+        }
+        // cells the trunk may run through or end in, and the points
+        let free = |x: usize, y: usize, point: bool| match self.map[(x, y)] {
+            Empty => true,
+            Terminal => point,
+            _ => false,
+        };
+        // the terminals at points, joining the existing ones
+        let terminal = |x: usize, y: usize, sides: [bool; 4]| {
+            let mut terminal = self.terminal(x, y).unwrap_or_default();
+            for direction in &[Direction::L, Direction::R, Direction::U, Direction::D] {
+                if sides[direction.index()] {
+                    terminal.connect(*direction);
+                }
             }
+            terminal
+        };
+        let keep_out = self.keep_out(&[options.net], &points);
+        if let Some((rule, _)) = &keep_out {
+            planar |= *rule != Rule::default();
         }
+        let kept_out = |changes: &ChangeSet| match &keep_out {
+            Some((_, keep_out)) => changes.changes.iter().any(|(x, y, _)| keep_out[(*x, *y)]),
+            None => false,
+        };
 
         let (mut min_x, mut min_y) = points[0];
         let (mut max_x, mut max_y) = points[0];
@@ -54,7 +84,7 @@ impl Maze {
             }
 
             // x = min_x
-            if self.map[min_x][*point_y] != Empty {
+            if !free(min_x, *point_y, on[0]) {
                 continue 'next_h;
             }
             if on[0] {
@@ -70,7 +100,7 @@ impl Maze {
             }
 
             // x = max_x
-            if self.map[max_x][*point_y] != Empty {
+            if !free(max_x, *point_y, on[max_x - min_x]) {
                 continue 'next_h;
             }
             if on[max_x - min_x] {
@@ -86,7 +116,7 @@ impl Maze {
             }
 
             for x in (min_x + 1)..(max_x) {
-                if self.map[x][*point_y] != Empty
+                if !free(x, *point_y, on[x - min_x])
                     && (on[x - min_x] || up[x - min_x] || down[x - min_x])
                 {
                     // non-LR
@@ -101,9 +131,9 @@ impl Maze {
                 } else if down[x - min_x] {
                     changes.push((x, *point_y, RDL));
                 } else {
-                    if self.map[x][*point_y] == Empty {
+                    if self.map[(x, *point_y)] == Empty {
                         changes.push((x, *point_y, LR));
-                    } else if self.map[x][*point_y] == UD {
+                    } else if self.map[(x, *point_y)] == UD {
                         changes.push((x, *point_y, Cross));
                     } else {
                         continue 'next_h;
@@ -117,19 +147,19 @@ impl Maze {
                     continue;
                 } else if new_y > point_y {
                     for y in (point_y + 1)..*new_y {
-                        if self.map[*new_x][y] == Empty {
+                        if self.map[(*new_x, y)] == Empty {
                             changes.push((*new_x, y, UD));
-                        } else if self.map[*new_x][y] == LR {
+                        } else if self.map[(*new_x, y)] == LR {
                             changes.push((*new_x, y, Cross));
                         } else {
                             continue 'next_h;
                         }
                     }
                 } else {
-                    for y in *new_y..*point_y {
-                        if self.map[*new_x][y] == Empty {
+                    for y in (new_y + 1)..*point_y {
+                        if self.map[(*new_x, y)] == Empty {
                             changes.push((*new_x, y, UD));
-                        } else if self.map[*new_x][y] == LR {
+                        } else if self.map[(*new_x, y)] == LR {
                             changes.push((*new_x, y, Cross));
                         } else {
                             continue 'next_h;
@@ -138,22 +168,29 @@ impl Maze {
                 }
             }
 
+            let mut terminals = vec![];
             for (x, y) in &points {
-                changes.push((*x, *y, Blocked));
+                changes.push((*x, *y, Terminal));
+                let sides = trunk_sides(&points, *point_y, (*x, *y));
+                terminals.push((*x, *y, terminal(*x, *y, sides)));
             }
 
             // remove duplicate assignments to one cell
-            changes.sort_by_key(|(x, y, _state)| (*x, *y));
-            changes.reverse();
-            changes.dedup_by_key(|(x, y, _state)| (*x, *y));
+            let changes = ChangeSet { changes, terminals }.normalize();
+            if !self.can_apply(&changes)
+                || planar && changes.changes.iter().any(|(_, _, state)| *state == Cross)
+                || kept_out(&changes)
+            {
+                continue 'next_h;
+            }
 
             match &ans {
                 Some(old) => {
-                    if old.changes.len() > changes.len() {
-                        ans = Some(ChangeSet { changes: changes })
+                    if old.len() > changes.len() {
+                        ans = Some(changes)
                     }
                 }
-                None => ans = Some(ChangeSet { changes: changes }),
+                None => ans = Some(changes),
             }
         }
 
@@ -175,7 +212,7 @@ impl Maze {
             }
 
             // y = min_y
-            if self.map[*point_x][min_y] != Empty {
+            if !free(*point_x, min_y, on[0]) {
                 continue 'next_v;
             }
             if on[0] {
@@ -191,7 +228,7 @@ impl Maze {
             }
 
             // y = max_y
-            if self.map[*point_x][max_y] != Empty {
+            if !free(*point_x, max_y, on[max_y - min_y]) {
                 continue 'next_v;
             }
             if on[max_y - min_y] {
@@ -207,7 +244,7 @@ impl Maze {
             }
 
             for y in (min_y + 1)..(max_y) {
-                if self.map[*point_x][y] != Empty
+                if !free(*point_x, y, on[y - min_y])
                     && (on[y - min_y] || right[y - min_y] || left[y - min_y])
                 {
                     // non-UD
@@ -222,9 +259,9 @@ impl Maze {
                 } else if left[y - min_y] {
                     changes.push((*point_x, y, DLU));
                 } else {
-                    if self.map[*point_x][y] == Empty {
+                    if self.map[(*point_x, y)] == Empty {
                         changes.push((*point_x, y, UD));
-                    } else if self.map[*point_x][y] == LR {
+                    } else if self.map[(*point_x, y)] == LR {
                         changes.push((*point_x, y, Cross));
                     } else {
                         continue 'next_v;
@@ -238,19 +275,19 @@ impl Maze {
                     continue;
                 } else if new_x > point_x {
                     for x in (point_x + 1)..*new_x {
-                        if self.map[x][*new_y] == Empty {
+                        if self.map[(x, *new_y)] == Empty {
                             changes.push((x, *new_y, LR));
-                        } else if self.map[x][*new_y] == UD {
+                        } else if self.map[(x, *new_y)] == UD {
                             changes.push((x, *new_y, Cross));
                         } else {
                             continue 'next_v;
                         }
                     }
                 } else {
-                    for x in *new_x..*point_x {
-                        if self.map[x][*new_y] == Empty {
+                    for x in (new_x + 1)..*point_x {
+                        if self.map[(x, *new_y)] == Empty {
                             changes.push((x, *new_y, LR));
-                        } else if self.map[x][*new_y] == UD {
+                        } else if self.map[(x, *new_y)] == UD {
                             changes.push((x, *new_y, Cross));
                         } else {
                             continue 'next_v;
@@ -259,26 +296,49 @@ impl Maze {
                 }
             }
 
+            let transposed: Vec<(usize, usize)> = points.iter().map(|(x, y)| (*y, *x)).collect();
+            let mut terminals = vec![];
             for (x, y) in &points {
-                changes.push((*x, *y, Blocked));
+                changes.push((*x, *y, Terminal));
+                // L, R, U, D of the transposed tree are D, U, R, L here
+                let [down, up, right, left] = trunk_sides(&transposed, *point_x, (*y, *x));
+                terminals.push((*x, *y, terminal(*x, *y, [left, right, up, down])));
             }
 
             // remove duplicate assignments to one cell
-            changes.sort_by_key(|(x, y, _state)| (*x, *y));
-            changes.reverse();
-            changes.dedup_by_key(|(x, y, _state)| (*x, *y));
+            let changes = ChangeSet { changes, terminals }.normalize();
+            if !self.can_apply(&changes)
+                || planar && changes.changes.iter().any(|(_, _, state)| *state == Cross)
+                || kept_out(&changes)
+            {
+                continue 'next_v;
+            }
 
             match &ans {
                 Some(old) => {
-                    if old.changes.len() > changes.len() {
-                        ans = Some(ChangeSet { changes: changes })
+                    if old.len() > changes.len() {
+                        ans = Some(changes)
                     }
                 }
-                None => ans = Some(ChangeSet { changes: changes }),
+                None => ans = Some(changes),
             }
         }
 
-        ans
+        let mut ans = ans.ok_or(RouteError::Unroutable)?;
+        for (x, y, terminal) in &mut ans.terminals {
+            if self.map[(*x, *y)] != Terminal {
+                terminal.pin = options.net;
+            }
+        }
+        Ok(ans)
+    }
+}
+
+#[wasm_bindgen]
+impl Maze {
+    /// Single Trunk Steiner Tree Algorithm
+    pub fn stst(&self, points: &Points) -> Option<ChangeSet> {
+        self.stst_with(&RouteOptions::default(), points).ok()
     }
 
     /// Single Trunk Steiner Tree Algorithm
@@ -293,6 +353,27 @@ impl Maze {
     }
 }
 
+/// Sides (L, R, U, D) of `point` connected in the single trunk tree of
+/// `points` with a horizontal trunk at y = `trunk`
+fn trunk_sides(points: &[(usize, usize)], trunk: usize, point: (usize, usize)) -> [bool; 4] {
+    let (x, y) = point;
+    let above = points
+        .iter()
+        .any(|(new_x, new_y)| *new_x == x && *new_y > y);
+    let below = points
+        .iter()
+        .any(|(new_x, new_y)| *new_x == x && *new_y < y);
+    if y == trunk {
+        let min_x = points.iter().map(|(x, _)| *x).min().unwrap();
+        let max_x = points.iter().map(|(x, _)| *x).max().unwrap();
+        [x > min_x, x < max_x, above, below]
+    } else if y > trunk {
+        [false, false, above, true]
+    } else {
+        [false, false, true, below]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,337 @@
+//! Terminals of routed nets
+use super::*;
+use std::collections::btree_map::Entry;
+
+/// A terminal cell and the directions its wires leave in
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Terminal {
+    /// Pin the terminal belongs to, 0 if none was assigned
+    pub pin: u32,
+    /// L, R, U, D
+    directions: [bool; 4],
+}
+
+impl Terminal {
+    pub fn new(pin: u32) -> Terminal {
+        Terminal {
+            pin,
+            directions: [false; 4],
+        }
+    }
+
+    /// Directions connected to a wire, L, R, U, D
+    pub fn directions(&self) -> [bool; 4] {
+        self.directions
+    }
+
+    pub fn connects(&self, direction: Direction) -> bool {
+        self.directions[direction.index()]
+    }
+
+    pub(crate) fn connect(&mut self, direction: Direction) {
+        self.directions[direction.index()] = true;
+    }
+
+    pub(crate) fn set_directions(&mut self, directions: [bool; 4]) {
+        self.directions = directions;
+    }
+}
+
+#[wasm_bindgen]
+impl Terminal {
+    pub fn left(&self) -> bool {
+        self.connects(Direction::L)
+    }
+
+    pub fn right(&self) -> bool {
+        self.connects(Direction::R)
+    }
+
+    pub fn up(&self) -> bool {
+        self.connects(Direction::U)
+    }
+
+    pub fn down(&self) -> bool {
+        self.connects(Direction::D)
+    }
+}
+
+impl fmt::Display for Terminal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the side of a terminal with a single wire
+        let ch = match self.directions {
+            [true, false, false, false] => '╸',
+            [false, true, false, false] => '╺',
+            [false, false, true, false] => '╹',
+            [false, false, false, true] => '╻',
+            _ => 'o',
+        };
+        write!(f, "{}", ch)
+    }
+}
+
+/// Terminals of one net being built by a router
+#[derive(Default)]
+pub(crate) struct Terminals {
+    terminals: BTreeMap<(usize, usize), Terminal>,
+}
+
+impl Terminals {
+    /// Add (x, y) to `changes` as a terminal, keeping the pin of a terminal
+    /// already in `maze`
+    pub fn add(
+        &mut self,
+        maze: &Maze,
+        changes: &mut Vec<(usize, usize, CellState)>,
+        x: usize,
+        y: usize,
+    ) {
+        if let Entry::Vacant(entry) = self.terminals.entry((x, y)) {
+            changes.push((x, y, CellState::Terminal));
+            entry.insert(maze.terminal(x, y).unwrap_or_default());
+        }
+    }
+
+    /// Connect the terminal at (x, y) to its neighbour in `direction`
+    pub fn connect(&mut self, x: usize, y: usize, direction: Direction) {
+        if let Some(terminal) = self.terminals.get_mut(&(x, y)) {
+            terminal.connect(direction);
+        }
+    }
+
+    pub fn finish(self, changes: Vec<(usize, usize, CellState)>) -> ChangeSet {
+        ChangeSet {
+            changes,
+            terminals: self
+                .terminals
+                .into_iter()
+                .map(|((x, y), terminal)| (x, y, terminal))
+                .collect(),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Maze {
+    /// Terminal at (x, y), if there is one
+    pub fn terminal(&self, x: usize, y: usize) -> Option<Terminal> {
+        match self.map[(x, y)] {
+            CellState::Terminal => Some(self.terminals.get(&(x, y)).copied().unwrap_or_default()),
+            _ => None,
+        }
+    }
+}
+
+impl Maze {
+    /// Changes making (x, y) an unconnected terminal
+    pub(crate) fn single_terminal(&self, x: usize, y: usize) -> ChangeSet {
+        let mut changes = vec![];
+        let mut terminals = Terminals::default();
+        terminals.add(self, &mut changes, x, y);
+        terminals.finish(changes)
+    }
+
+    /// Directions (x, y) connects to, L, R, U, D
+    pub(crate) fn directions(&self, x: usize, y: usize) -> [bool; 4] {
+        match self.terminal(x, y) {
+            Some(terminal) => terminal.directions,
+            None => self.map[(x, y)].extract(),
+        }
+    }
+
+    /// Whether a wire may leave (x, y) to `direction`
+    #[inline]
+    pub(crate) fn allows(&self, x: usize, y: usize, direction: Direction) -> bool {
+        if let Some(orientations) = &self.orientations {
+            if !orientations[(x, y)].allows(direction) {
+                return false;
+            }
+        }
+        self.access.is_empty()
+            || self
+                .access
+                .get(&(x, y))
+                .is_none_or(|sides| sides[direction.index()])
+    }
+
+    /// Whether a wire may run from (x, y) to its neighbour (new_x, new_y)
+    /// in `direction`, through an unblocked edge and allowed sides
+    #[inline]
+    pub(crate) fn can_connect(
+        &self,
+        x: usize,
+        y: usize,
+        new_x: usize,
+        new_y: usize,
+        direction: Direction,
+    ) -> bool {
+        !self.walled(x, y, direction)
+            && self.allows(x, y, direction)
+            && self.allows(new_x, new_y, direction.opposite())
+    }
+
+    /// Whether the cells changed by `changes` only use sides they allow and
+    /// edges that are not blocked
+    pub(crate) fn can_apply(&self, changes: &ChangeSet) -> bool {
+        let terminals: BTreeMap<(usize, usize), Terminal> = changes
+            .terminals
+            .iter()
+            .map(|(x, y, terminal)| ((*x, *y), *terminal))
+            .collect();
+        changes.changes.iter().all(|(x, y, state)| {
+            let used = match terminals.get(&(*x, *y)) {
+                Some(terminal) if *state == CellState::Terminal => terminal.directions,
+                _ => state.extract(),
+            };
+            [Direction::L, Direction::R, Direction::U, Direction::D]
+                .iter()
+                .all(|direction| {
+                    !used[direction.index()]
+                        || (self.allows(*x, *y, *direction) && !self.walled(*x, *y, *direction))
+                })
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl Maze {
+    /// Only let wires enter and leave (x, y) through the given sides, for
+    /// pins that must be reached from one side
+    pub fn set_access(
+        &mut self,
+        x: usize,
+        y: usize,
+        left: bool,
+        right: bool,
+        up: bool,
+        down: bool,
+    ) {
+        assert!(x < self.m && y < self.n);
+        self.access.insert((x, y), [left, right, up, down]);
+    }
+
+    /// Let wires use every side of (x, y) again
+    pub fn clear_access(&mut self, x: usize, y: usize) {
+        self.access.remove(&(x, y));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terminal() {
+        use Direction::*;
+        let mut maze = Maze::new(3, 3);
+        assert!(maze.lee_mut(0, 0, 2, 0));
+        assert!(maze.lee_mut(0, 1, 1, 1));
+        assert!(maze.lee_multi_mut(&Points::new(&[(0, 2), (1, 2), (2, 1), (2, 2)])));
+        println!("{}", maze);
+        assert!(maze.verify());
+        assert_eq!(maze.get(0, 0), CellState::Terminal);
+        assert_eq!(
+            maze.terminal(0, 0).unwrap().directions(),
+            [false, true, false, false]
+        );
+        assert!(maze.terminal(2, 0).unwrap().connects(L));
+        assert!(maze.terminal(0, 1).unwrap().connects(R));
+        assert!(maze.terminal(1, 1).unwrap().connects(L));
+        // (1, 2) is between (0, 2) and (2, 2)
+        let middle = maze.terminal(1, 2).unwrap();
+        assert!(middle.connects(L) && middle.connects(R));
+        assert!(maze.terminal(2, 1).unwrap().connects(U));
+        assert_eq!(maze.terminal(1, 0), None);
+
+        // a wire ending next to a blockage is not connected
+        let mut maze = Maze::new(3, 1);
+        maze.fill_mut(0, 0, 0, 0);
+        let mut end = Terminal::new(0);
+        end.connect(L);
+        let changes = ChangeSet {
+            changes: vec![(1, 0, CellState::LR), (2, 0, CellState::Terminal)],
+            terminals: vec![(2, 0, end)],
+        };
+        maze.apply(&changes);
+        assert!(!maze.verify());
+    }
+
+    #[test]
+    fn join_terminal() {
+        use Direction::*;
+        let mut maze = Maze::new(5, 5);
+        assert!(maze.lee_mut(0, 0, 4, 0));
+        // every router starts from the terminal at (0, 0), and ends at it
+        let routes = [
+            maze.lee(0, 0, 0, 4),
+            maze.lee_minimum_crossing(0, 0, 0, 4),
+            maze.lee_minimum_edge_effect(0, 0, 0, 4),
+            maze.hadlock(0, 0, 0, 4),
+            maze.mikami_tabuchi(0, 0, 0, 4),
+            maze.lee_multi(&Points::new(&[(0, 0), (0, 4)])),
+            maze.mikami_tabuchi_multi(&Points::new(&[(0, 0), (0, 4)])),
+            maze.stst(&Points::new(&[(0, 0), (0, 4)])),
+            maze.lee(0, 4, 0, 0),
+            maze.lee_minimum_crossing(0, 4, 0, 0),
+            maze.lee_minimum_edge_effect(0, 4, 0, 0),
+            maze.hadlock(0, 4, 0, 0),
+            maze.mikami_tabuchi(0, 4, 0, 0),
+            maze.lee_multi(&Points::new(&[(0, 4), (0, 0)])),
+            maze.mikami_tabuchi_multi(&Points::new(&[(0, 4), (0, 0)])),
+            maze.stst(&Points::new(&[(0, 4), (0, 0), (2, 4)])),
+        ];
+        for changes in &routes {
+            let mut routed = maze.clone();
+            routed.apply(changes.as_ref().unwrap());
+            println!("{}", routed);
+            assert!(routed.verify());
+            let terminal = routed.terminal(0, 0).unwrap();
+            assert!(terminal.connects(R) && terminal.connects(U));
+        }
+
+        // a multi-terminal route joining the wire at its far end
+        let routes = [
+            maze.lee_multi(&Points::new(&[(4, 0), (0, 4)])),
+            maze.mikami_tabuchi_multi(&Points::new(&[(4, 0), (0, 4)])),
+        ];
+        for changes in &routes {
+            let mut routed = maze.clone();
+            routed.apply(changes.as_ref().unwrap());
+            println!("{}", routed);
+            assert!(routed.verify());
+            assert!(routed.terminal(4, 0).unwrap().connects(L));
+        }
+    }
+
+    #[test]
+    fn access() {
+        let mut maze = Maze::new(5, 3);
+        maze.set_access(0, 0, false, false, true, false);
+        maze.set_access(4, 0, false, false, false, true);
+        assert_eq!(maze.lee(0, 0, 4, 0), None);
+
+        maze.clear_access(4, 0);
+        let routes = [
+            maze.lee(0, 0, 4, 0),
+            maze.lee_minimum_crossing(0, 0, 4, 0),
+            maze.hadlock(0, 0, 4, 0),
+            maze.mikami_tabuchi(0, 0, 4, 0),
+            maze.lee_multi(&Points::new(&[(0, 0), (4, 0)])),
+            maze.stst(&Points::new(&[(0, 0), (2, 2)])),
+        ];
+        for changes in &routes {
+            let mut routed = maze.clone();
+            routed.apply(changes.as_ref().unwrap());
+            println!("{}", routed);
+            assert!(routed.verify());
+            assert_eq!(
+                routed.terminal(0, 0).unwrap().directions(),
+                [false, false, true, false]
+            );
+            assert!(routed.to_string().lines().last().unwrap().starts_with('╹'));
+        }
+        // the trunk along y = 0 would leave (0, 0) to the right
+        assert_eq!(maze.stst(&Points::new(&[(0, 0), (2, 0)])), None);
+    }
+}
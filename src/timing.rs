@@ -0,0 +1,428 @@
+//! Elmore delay of routed trees and timing-driven routing
+use super::*;
+use std::collections::BTreeSet;
+
+/// Resistance and capacitance of routes, in any consistent units
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parasitics {
+    /// resistance of a wire per step between adjacent cells
+    pub wire_r: f64,
+    /// capacitance of a wire per step between adjacent cells
+    pub wire_c: f64,
+    /// resistance added where a route crosses another wire
+    pub cross_r: f64,
+    /// capacitance added where a route crosses another wire
+    pub cross_c: f64,
+    /// load of every sink not in `loads`
+    pub sink_c: f64,
+    /// load of single sinks
+    pub loads: Vec<((usize, usize), f64)>,
+}
+
+impl Default for Parasitics {
+    fn default() -> Parasitics {
+        Parasitics {
+            wire_r: 1.0,
+            wire_c: 1.0,
+            cross_r: 0.0,
+            cross_c: 0.0,
+            sink_c: 0.0,
+            loads: vec![],
+        }
+    }
+}
+
+impl Parasitics {
+    /// Weights trading a crossing against a step of wire by their
+    /// resistance, in tenths of a step
+    fn crossing_weights(&self, weights: &Weights) -> Weights {
+        if self.wire_r <= 0.0 {
+            return Weights::default();
+        }
+        Weights {
+            length: 10,
+            crossing: (10.0 * self.cross_r / self.wire_r).round() as usize,
+            edge: 0,
+            coupling: weights.coupling,
+        }
+    }
+
+    fn load(&self, sink: (usize, usize)) -> f64 {
+        self.loads
+            .iter()
+            .find(|(cell, _)| *cell == sink)
+            .map_or(self.sink_c, |(_, load)| *load)
+    }
+}
+
+/// Route found by `Maze::timing_driven_with`
+#[derive(Clone, Debug)]
+pub struct TimingReport {
+    pub changes: ChangeSet,
+    /// Elmore delay from the source to every sink, in order of the sinks
+    pub delays: Vec<((usize, usize), f64)>,
+    /// largest delay of `delays`
+    pub max_delay: f64,
+    /// name of the router that found the route
+    pub router: String,
+}
+
+impl Maze {
+    /// Elmore delay from `source` to every other terminal of a route
+    /// computed against this maze, `None` if `source` is not on the route.
+    /// Every step is a wire segment with half of its capacitance at either
+    /// end, crossings add their resistance to the step into them.
+    pub fn elmore(
+        &self,
+        changes: &ChangeSet,
+        source: (usize, usize),
+        parasitics: &Parasitics,
+    ) -> Option<Vec<((usize, usize), f64)>> {
+        let states = changes.final_states();
+        if !states.contains_key(&source) {
+            return None;
+        }
+        let terminals: BTreeMap<(usize, usize), Terminal> = changes
+            .terminals
+            .iter()
+            .map(|(x, y, terminal)| ((*x, *y), *terminal))
+            .collect();
+        // sides used by the route in every cell
+        let sides = |cell: (usize, usize)| {
+            let new = match (states[&cell], terminals.get(&cell)) {
+                (CellState::Terminal, Some(terminal)) => terminal.directions(),
+                (state, _) => state.extract(),
+            };
+            let old = self.directions(cell.0, cell.1);
+            let mut added = [false; 4];
+            for i in 0..4 {
+                added[i] = new[i] && !old[i];
+            }
+            added
+        };
+        let crossing = |cell: (usize, usize)| {
+            states[&cell] == CellState::Cross && self.map[cell] != CellState::Empty
+        };
+
+        // cells from the source outwards, with their parent
+        let mut order = vec![(source, None)];
+        let mut seen = BTreeSet::new();
+        seen.insert(source);
+        let mut i = 0;
+        while i < order.len() {
+            let (cell, _) = order[i];
+            let added = sides(cell);
+            for direction in &[Direction::L, Direction::R, Direction::U, Direction::D] {
+                if !added[direction.index()] {
+                    continue;
+                }
+                if let Some(next) = self.map.neighbor(cell.0, cell.1, *direction) {
+                    if states.contains_key(&next) && seen.insert(next) {
+                        order.push((next, Some(i)));
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        let mut cap: Vec<f64> = order
+            .iter()
+            .map(|(cell, _)| {
+                let mut cap = if crossing(*cell) {
+                    parasitics.cross_c
+                } else {
+                    0.0
+                };
+                if *cell != source && terminals.contains_key(cell) {
+                    cap += parasitics.load(*cell);
+                }
+                cap
+            })
+            .collect();
+        for (i, (_, parent)) in order.iter().enumerate() {
+            if let Some(parent) = parent {
+                cap[i] += parasitics.wire_c / 2.0;
+                cap[*parent] += parasitics.wire_c / 2.0;
+            }
+        }
+        // capacitance downstream of every cell
+        for i in (0..order.len()).rev() {
+            if let Some(parent) = order[i].1 {
+                cap[parent] += cap[i];
+            }
+        }
+        let mut delay = vec![0.0; order.len()];
+        for (i, (cell, parent)) in order.iter().enumerate() {
+            if let Some(parent) = parent {
+                let mut r = parasitics.wire_r;
+                if crossing(*cell) {
+                    r += parasitics.cross_r;
+                }
+                delay[i] = delay[*parent] + r * cap[i];
+            }
+        }
+        Some(
+            order
+                .iter()
+                .zip(delay)
+                .filter(|((cell, _), _)| *cell != source && terminals.contains_key(cell))
+                .map(|((cell, _), delay)| (*cell, delay))
+                .collect(),
+        )
+    }
+
+    /// Route a net from `source` to `sinks`, keeping the candidate route
+    /// with the smallest largest Elmore delay to a sink. The candidates are
+    /// a tree grown by Lee's algorithm from the source, which keeps every
+    /// sink as close to the source as it can, a tree joining the sinks one
+    /// by one with `lee_minimum_crossing_with`, which weighs a crossing
+    /// against a step by `cross_r` and `wire_r`, and the routers of
+    /// `Maze::multi_terminal_with`. Scratch buffers in `ctx` are reused
+    /// within the limits of `options`. Ties go to the shorter route, then
+    /// the earlier candidate. Routes other than the candidates may be
+    /// faster.
+    pub fn timing_driven_with(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        source: (usize, usize),
+        sinks: &Points,
+        parasitics: &Parasitics,
+    ) -> Result<TimingReport, RouteError> {
+        let mut points = vec![source];
+        points.extend(sinks.get().into_iter().filter(|sink| *sink != source));
+        if points.len() == 1 {
+            return Ok(TimingReport {
+                changes: self.single_terminal(source.0, source.1),
+                delays: vec![],
+                max_delay: 0.0,
+                router: String::from("SourceTree"),
+            });
+        }
+
+        let mut candidates = vec![(
+            String::from("SourceTree"),
+            self.search_window(ctx, options, &points, |maze, ctx| {
+                maze.lee_multi_search(ctx, &points)
+            }),
+        )];
+        let weighted = RouteOptions {
+            weights: parasitics.crossing_weights(&options.weights),
+            ..options.clone()
+        };
+        candidates.push((
+            String::from("LeeMinimumCrossing"),
+            self.crossing_tree(ctx, &weighted, &points),
+        ));
+        let net = Net::MultiPin(Points::new(&points));
+        for algorithm in &class::MULTI_PIN {
+            candidates.push((algorithm.name(), algorithm.route(self, ctx, options, &net)));
+        }
+
+        let mut best: Option<TimingReport> = None;
+        let mut err = RouteError::Unroutable;
+        for (router, result) in candidates {
+            let changes = match result {
+                Ok(changes) => changes,
+                Err(new_err) => {
+                    err = new_err;
+                    continue;
+                }
+            };
+            let delays = match self.elmore(&changes, source, parasitics) {
+                Some(delays) => delays,
+                None => continue,
+            };
+            let mut ordered = vec![];
+            for sink in &points[1..] {
+                if let Some(delay) = delays.iter().find(|(cell, _)| cell == sink) {
+                    ordered.push(*delay);
+                }
+            }
+            if ordered.len() + 1 < points.len() {
+                continue;
+            }
+            let max_delay = ordered.iter().map(|(_, delay)| *delay).fold(0.0, f64::max);
+            if best.as_ref().is_none_or(|best| {
+                (max_delay, changes.len()) < (best.max_delay, best.changes.len())
+            }) {
+                best = Some(TimingReport {
+                    changes,
+                    delays: ordered,
+                    max_delay,
+                    router,
+                });
+            }
+        }
+        best.ok_or(err)
+    }
+
+    /// Tree joining every point after the first, nearest first, to the
+    /// closest terminal already joined with `lee_minimum_crossing_with`
+    fn crossing_tree(
+        &self,
+        ctx: &mut RouterContext,
+        options: &RouteOptions,
+        points: &[(usize, usize)],
+    ) -> Result<ChangeSet, RouteError> {
+        let distance = |a: (usize, usize), b: (usize, usize)| a.0.abs_diff(b.0) + a.1.abs_diff(b.1);
+        let mut sinks = points[1..].to_vec();
+        sinks.sort_by_key(|sink| distance(points[0], *sink));
+        // the sinks are kept free until they are joined
+        let mut work = self.clone();
+        let mut blocked = ChangeSet::default();
+        for (x, y) in &sinks {
+            if work.map[(*x, *y)] == CellState::Empty {
+                blocked.changes.push((*x, *y, CellState::Blocked));
+            }
+        }
+        work.apply(&blocked);
+        let mut joined = vec![points[0]];
+        let mut changes = ChangeSet::default();
+        for sink in sinks {
+            if blocked.changes.iter().any(|(x, y, _)| (*x, *y) == sink) {
+                work.apply(&ChangeSet {
+                    changes: vec![(sink.0, sink.1, CellState::Empty)],
+                    terminals: vec![],
+                });
+            }
+            joined.sort_by_key(|cell| distance(*cell, sink));
+            let mut result = Err(RouteError::Unroutable);
+            for (x, y) in &joined {
+                result = work.lee_minimum_crossing_with(ctx, options, *x, *y, sink.0, sink.1);
+                if result.is_ok() {
+                    break;
+                }
+            }
+            let route = result?;
+            work.apply(&route);
+            changes = changes.compose(&route);
+            joined.push(sink);
+        }
+        Ok(changes)
+    }
+
+    /// Like `timing_driven_with` with the default options
+    pub fn timing_driven(
+        &self,
+        source: (usize, usize),
+        sinks: &Points,
+        parasitics: &Parasitics,
+    ) -> Option<TimingReport> {
+        let options = RouteOptions::default();
+        self.timing_driven_with(
+            &mut RouterContext::new(self),
+            &options,
+            source,
+            sinks,
+            parasitics,
+        )
+        .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn elmore() {
+        let maze = Maze::new(5, 3);
+        let changes = maze.lee(0, 1, 4, 1).unwrap();
+        let parasitics = Parasitics {
+            sink_c: 2.0,
+            ..Parasitics::default()
+        };
+        // four steps of r = 1, c = 1 into a load of 2
+        let delays = maze.elmore(&changes, (0, 1), &parasitics).unwrap();
+        assert_eq!(delays, vec![((4, 1), 0.5 * 16.0 + 2.0 * 4.0)]);
+        assert_eq!(maze.elmore(&changes, (2, 2), &parasitics), None);
+
+        // crossing a wire adds resistance and capacitance
+        let mut crossed = maze.clone();
+        crossed.two_terminal_mut(2, 0, 2, 2);
+        let changes = crossed.lee(0, 1, 4, 1).unwrap();
+        assert_eq!(changes.crossings(), 1);
+        let plain = crossed.elmore(&changes, (0, 1), &parasitics).unwrap();
+        assert_eq!(plain, delays);
+        let parasitics = Parasitics {
+            cross_r: 1.0,
+            cross_c: 1.0,
+            ..parasitics
+        };
+        let delays = crossed.elmore(&changes, (0, 1), &parasitics).unwrap();
+        // the crossing loads the first two steps, and its resistance sees
+        // everything from the crossing on
+        assert_eq!(delays[0].1, 16.0 + 2.0 + 5.5);
+    }
+
+    #[test]
+    fn timing_driven() {
+        let maze = Maze::new(9, 9);
+        let sinks = Points::new(&[(8, 0), (8, 8), (0, 8)]);
+        let report = maze
+            .timing_driven((0, 0), &sinks, &Parasitics::default())
+            .unwrap();
+        let mut routed = maze.clone();
+        routed.apply(&report.changes);
+        println!("{}\n{:?}", routed, report.delays);
+        assert!(routed.verify());
+        assert_eq!(report.delays.len(), 3);
+        // no other router does better
+        let mut ctx = RouterContext::new(&maze);
+        let net = Net::MultiPin(Points::new(&[(0, 0), (8, 0), (8, 8), (0, 8)]));
+        for algorithm in &class::MULTI_PIN {
+            let changes = algorithm
+                .route(&maze, &mut ctx, &RouteOptions::default(), &net)
+                .unwrap();
+            let delays = maze
+                .elmore(&changes, (0, 0), &Parasitics::default())
+                .unwrap();
+            assert!(delays.iter().any(|(_, delay)| *delay >= report.max_delay));
+        }
+
+        // a costly crossing makes going around the wire faster
+        let mut maze = Maze::new(7, 5);
+        maze.two_terminal_mut(3, 0, 3, 3);
+        let sinks = Points::new(&[(6, 1)]);
+        let parasitics = Parasitics {
+            cross_r: 20.0,
+            ..Parasitics::default()
+        };
+        let report = maze.timing_driven((0, 1), &sinks, &parasitics).unwrap();
+        assert_eq!(report.router, "LeeMinimumCrossing");
+        assert_eq!(report.changes.crossings(), 0);
+        let report = maze
+            .timing_driven((0, 1), &sinks, &Parasitics::default())
+            .unwrap();
+        assert_eq!(report.changes.crossings(), 1);
+    }
+
+    quickcheck! {
+        fn qc_timing_driven(m: usize, n: usize, source: (usize, usize), points: Vec<(usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 30 || n > 30 {
+                return true
+            }
+
+            let maze = Maze::new(m, n);
+            let source = (source.0 % m, source.1 % n);
+            let points: Vec<(usize, usize)> = points.iter().map(|(x, y)| (x % m, y % n)).collect();
+            let parasitics = Parasitics::default();
+            match maze.timing_driven(source, &Points::new(&points), &parasitics) {
+                Some(report) => {
+                    let mut routed = maze.clone();
+                    routed.apply(&report.changes);
+                    routed.verify()
+                        && report.delays.iter().all(|(_, delay)| *delay <= report.max_delay)
+                        && report.delays.iter().all(|(sink, _)| points.contains(sink))
+                }
+                None => true,
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Blocked edges between adjacent cells
+use super::*;
+
+/// Bit of a cell in `Maze::walls` blocking the edge to its right neighbour
+const RIGHT: u8 = 1;
+/// Bit of a cell in `Maze::walls` blocking the edge to its upper neighbour
+const UP: u8 = 2;
+
+impl Maze {
+    /// Cell and bit of `walls` storing the edge between (x, y) and its
+    /// neighbour in `direction`
+    fn edge(&self, x: usize, y: usize, direction: Direction) -> Option<((usize, usize), u8)> {
+        use Direction::*;
+        let neighbor = self.map.neighbor(x, y, direction)?;
+        Some(match direction {
+            L => (neighbor, RIGHT),
+            R => ((x, y), RIGHT),
+            U => ((x, y), UP),
+            D => (neighbor, UP),
+        })
+    }
+
+    /// Whether the edge between (x, y) and its neighbour in `direction` is
+    /// blocked
+    #[inline]
+    pub(crate) fn walled(&self, x: usize, y: usize, direction: Direction) -> bool {
+        match (&self.walls, self.edge(x, y, direction)) {
+            (Some(walls), Some((cell, bit))) => walls[cell] & bit != 0,
+            _ => false,
+        }
+    }
+
+    fn set_edge(&mut self, x1: usize, y1: usize, x2: usize, y2: usize, blocked: bool) {
+        let direction = [Direction::L, Direction::R, Direction::U, Direction::D]
+            .iter()
+            .copied()
+            .find(|direction| self.map.neighbor(x1, y1, *direction) == Some((x2, y2)))
+            .expect("cells are not adjacent");
+        let (cell, bit) = self.edge(x1, y1, direction).unwrap();
+        let (m, n) = (self.m, self.n);
+        let walls = self.walls.get_or_insert_with(|| Grid::new(m, n, 0));
+        if blocked {
+            walls[cell] |= bit;
+        } else {
+            walls[cell] &= !bit;
+        }
+    }
+
+    /// Whether any edge is blocked
+    pub(crate) fn has_walls(&self) -> bool {
+        match &self.walls {
+            Some(walls) => (0..self.m).any(|x| (0..self.n).any(|y| walls[(x, y)] != 0)),
+            None => false,
+        }
+    }
+
+    /// Draw the maze with a gap between cells showing the blocked edges and
+    /// the wires running across the others
+    pub(crate) fn fmt_walls(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Direction::*;
+        for y in (0..self.n).rev() {
+            for x in 0..self.m {
+                match self.terminal(x, y) {
+                    Some(terminal) => write!(f, "{}", terminal)?,
+                    None => write!(f, "{}", self.map[(x, y)])?,
+                }
+                if x + 1 < self.m {
+                    let ch = if self.walled(x, y, R) {
+                        '│'
+                    } else if self.directions(x, y)[R.index()] {
+                        '━'
+                    } else {
+                        ' '
+                    };
+                    write!(f, "{}", ch)?;
+                }
+            }
+            writeln!(f)?;
+            if y > 0 {
+                for x in 0..self.m {
+                    let ch = if self.walled(x, y, D) {
+                        '─'
+                    } else if self.directions(x, y)[D.index()] {
+                        '┃'
+                    } else {
+                        ' '
+                    };
+                    write!(f, "{}", ch)?;
+                    if x + 1 < self.m {
+                        write!(f, " ")?;
+                    }
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+impl Maze {
+    /// Block the edge between the adjacent cells (x1, y1) and (x2, y2), so no
+    /// wire runs from one to the other
+    pub fn block_edge(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) {
+        self.set_edge(x1, y1, x2, y2, true);
+    }
+
+    /// Unblock the edge between the adjacent cells (x1, y1) and (x2, y2)
+    pub fn unblock_edge(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) {
+        self.set_edge(x1, y1, x2, y2, false);
+    }
+
+    /// Whether the edge between the adjacent cells (x1, y1) and (x2, y2) is
+    /// blocked
+    pub fn edge_blocked(&self, x1: usize, y1: usize, x2: usize, y2: usize) -> bool {
+        [Direction::L, Direction::R, Direction::U, Direction::D]
+            .iter()
+            .any(|direction| {
+                self.map.neighbor(x1, y1, *direction) == Some((x2, y2))
+                    && self.walled(x1, y1, *direction)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn walls() {
+        let mut maze = Maze::new(3, 3);
+        maze.block_edge(0, 0, 1, 0);
+        maze.block_edge(1, 1, 0, 1);
+        maze.block_edge(2, 0, 2, 1);
+        assert!(maze.edge_blocked(1, 0, 0, 0));
+        assert!(!maze.edge_blocked(0, 0, 0, 1));
+
+        let routes = [
+            maze.lee(0, 0, 2, 0),
+            maze.lee_minimum_crossing(0, 0, 2, 0),
+            maze.lee_minimum_edge_effect(0, 0, 2, 0),
+            maze.hadlock(0, 0, 2, 0),
+            maze.mikami_tabuchi(0, 0, 2, 0),
+            maze.lee_multi(&Points::new(&[(0, 0), (2, 0)])),
+            maze.mikami_tabuchi_multi(&Points::new(&[(0, 0), (2, 0)])),
+        ];
+        for changes in &routes {
+            let mut routed = maze.clone();
+            routed.apply(changes.as_ref().unwrap());
+            assert!(routed.verify());
+            assert_eq!(routed.to_string(), "┏━┓ .\n┃ ┃  \n┃│┃ .\n┃ ┃ ─\n╹│┗━╸\n");
+        }
+        // the trunk along y = 0 crosses the wall
+        assert_eq!(maze.stst(&Points::new(&[(0, 0), (1, 0)])), None);
+        assert!(maze.stst(&Points::new(&[(0, 2), (1, 2)])).is_some());
+
+        maze.unblock_edge(0, 0, 1, 0);
+        maze.unblock_edge(0, 1, 1, 1);
+        maze.unblock_edge(2, 0, 2, 1);
+        assert_eq!(maze.to_string(), Maze::new(3, 3).to_string());
+    }
+
+    quickcheck! {
+        fn qc_walls(m: usize, n: usize, walls: Vec<(usize, usize, bool)>, points: Vec<(usize, usize, usize, usize)>) -> bool {
+            if m == 0 || n == 0 {
+                return true;
+            }
+            // check oom
+            if m > 100 || n > 100 {
+                return true
+            }
+
+            let mut maze = Maze::new(m, n);
+            for (x, y, up) in walls {
+                let (x, y) = (x % m, y % n);
+                if up && y + 1 < n {
+                    maze.block_edge(x, y, x, y + 1);
+                } else if !up && x + 1 < m {
+                    maze.block_edge(x, y, x + 1, y);
+                }
+            }
+            for (x1, y1, x2, y2) in points {
+                maze.two_terminal_mut(x1 % m, y1 % n, x2 % m, y2 % n);
+            }
+            maze.verify()
+        }
+    }
+}